@@ -0,0 +1,119 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::parser::{detect_format, Format};
+
+/// Thread-safe LRU cache of [`detect_format`] results, for services that
+/// see the same small payloads over and over.
+///
+/// Entries are keyed by a 64-bit hash of the content rather than the
+/// content itself, so memory use is bounded by `capacity` alone.
+#[derive(Debug)]
+pub struct FormatCache {
+    capacity: usize,
+    state: Mutex<CacheState>,
+    scans: AtomicUsize,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    formats: HashMap<u64, Format>,
+    /// Keys from least to most recently used.
+    order: VecDeque<u64>,
+}
+
+impl FormatCache {
+    /// A cache holding at most `capacity` formats.  A capacity of 0
+    /// disables caching.
+    pub fn new(capacity: usize) -> Self {
+        FormatCache {
+            capacity,
+            state: Mutex::new(CacheState::default()),
+            scans: AtomicUsize::new(0),
+        }
+    }
+
+    /// Detect the format of `content`, scanning it only on a cache miss.
+    pub fn detect(&self, content: &str) -> Format {
+        let key = hash(content);
+        if let Some(format) = self.lookup(key) {
+            return format;
+        }
+
+        self.scans.fetch_add(1, Ordering::Relaxed);
+        let format = detect_format(content);
+        if self.capacity > 0 {
+            let mut state = self.state.lock().unwrap();
+            if state.formats.insert(key, format).is_none() {
+                state.order.push_back(key);
+            }
+            while state.order.len() > self.capacity {
+                if let Some(evicted) = state.order.pop_front() {
+                    state.formats.remove(&evicted);
+                }
+            }
+        }
+        format
+    }
+
+    /// Number of times `detect` had to scan its input.
+    pub fn scans(&self) -> usize {
+        self.scans.load(Ordering::Relaxed)
+    }
+
+    /// Number of cached formats.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().formats.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lookup(&self, key: u64) -> Option<Format> {
+        let mut state = self.state.lock().unwrap();
+        let format = *state.formats.get(&key)?;
+        if let Some(pos) = state.order.iter().position(|&k| k == key) {
+            state.order.remove(pos);
+            state.order.push_back(key);
+        }
+        Some(format)
+    }
+}
+
+fn hash(content: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hit_does_not_rescan() {
+        let cache = FormatCache::new(2);
+        assert_eq!(cache.detect("a,b\n1,2"), Format::Csv);
+        assert_eq!(cache.detect("a,b\n1,2"), Format::Csv);
+        assert_eq!(cache.scans(), 1);
+    }
+
+    #[test]
+    fn least_recently_used_entry_is_evicted() {
+        let cache = FormatCache::new(2);
+        cache.detect("a = 1");
+        cache.detect("a,b");
+        cache.detect("a = 1");
+        cache.detect("name:\n  x: 1");
+        assert_eq!(cache.len(), 2);
+
+        cache.detect("a = 1");
+        assert_eq!(cache.scans(), 3);
+        cache.detect("a,b");
+        assert_eq!(cache.scans(), 4);
+    }
+}
@@ -0,0 +1,398 @@
+use std::collections::HashMap;
+
+use crate::parser::{parse_key_value_config_strict, ConfigOptions};
+use crate::processor::ParsedData;
+
+/// Convert a `Config` into a two-column `CsvTable` with headers
+/// `["key", "value"]`.
+///
+/// Rows are sorted by key so the output is deterministic.  Returns
+/// `Err` if `data` is not a `Config`.
+pub fn config_to_table(data: &ParsedData) -> Result<ParsedData, String> {
+    let map = match data {
+        ParsedData::Config(map) => map,
+        _ => return Err("config_to_table expects a Config".into()),
+    };
+
+    let mut rows: Vec<Vec<String>> = map
+        .iter()
+        .map(|(k, v)| vec![k.clone(), v.clone()])
+        .collect();
+    rows.sort();
+
+    Ok(ParsedData::CsvTable {
+        headers: vec!["key".into(), "value".into()],
+        rows,
+    })
+}
+
+/// Options for [`json_to_table`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TableOptions {
+    /// Require every JSON object to have the same keys as the first,
+    /// instead of taking the union of all keys.
+    pub json_strict_keys: bool,
+}
+
+/// Convert `JsonRecords` into a `CsvTable` with one row per object.
+///
+/// The headers are the union of all objects' keys, sorted, and missing
+/// values become empty cells.  With `json_strict_keys` set, the first
+/// object whose key set differs from the first object's is reported by
+/// index instead.  Returns `Err` if `data` is not `JsonRecords`.
+pub fn json_to_table(data: &ParsedData, options: &TableOptions) -> Result<ParsedData, String> {
+    let records = match data {
+        ParsedData::JsonRecords(records) => records,
+        _ => return Err("json_to_table expects JsonRecords".into()),
+    };
+
+    let mut headers: Vec<String> = Vec::new();
+    if let Some(first) = records.first() {
+        let expected = key_set(first);
+        for (i, record) in records.iter().enumerate() {
+            let keys = key_set(record);
+            if options.json_strict_keys && keys != expected {
+                return Err(format!(
+                    "Object {} has keys {:?}, expected {:?}",
+                    i, keys, expected
+                ));
+            }
+            headers.extend(keys.into_iter().cloned());
+        }
+    }
+    headers.sort_unstable();
+    headers.dedup();
+
+    let rows = records
+        .iter()
+        .map(|record| {
+            headers
+                .iter()
+                .map(|h| record.get(h).cloned().unwrap_or_default())
+                .collect()
+        })
+        .collect();
+    Ok(ParsedData::CsvTable { headers, rows })
+}
+
+/// The keys of `record`, sorted.
+fn key_set(record: &HashMap<String, String>) -> Vec<&String> {
+    let mut keys: Vec<&String> = record.keys().collect();
+    keys.sort_unstable();
+    keys
+}
+
+/// Merge two `Config`s, with keys in `overlay` overriding `base`.
+///
+/// Returns `Err` if either input is not a `Config`.
+pub fn merge(base: &ParsedData, overlay: &ParsedData) -> Result<ParsedData, String> {
+    resolve_chain(&[base, overlay])
+}
+
+/// Fold several `Config`s left-to-right, later ones overriding earlier
+/// ones, e.g. `defaults.ini`, then `app.ini`, then `host.ini`.
+///
+/// Returns `Err` naming the position of the first input that is not a
+/// `Config`.  An empty chain resolves to an empty `Config`.
+pub fn resolve_chain<C>(configs: &[C]) -> Result<ParsedData, String>
+where
+    C: std::borrow::Borrow<ParsedData>,
+{
+    let mut resolved = HashMap::new();
+    for (i, config) in configs.iter().enumerate() {
+        match config.borrow() {
+            ParsedData::Config(map) => {
+                resolved.extend(map.iter().map(|(k, v)| (k.clone(), v.clone())));
+            }
+            _ => return Err(format!("Input {} in the chain is not a Config", i)),
+        }
+    }
+    Ok(ParsedData::Config(resolved))
+}
+
+/// Parse `defaults`, an INI string typically baked into the binary
+/// with `include_str!`, and layer `user` on top of it, so that keys the
+/// user leaves out fall back to their defaults.
+///
+/// Returns `Err` if `defaults` has a malformed line or `user` is not a
+/// `Config`.
+pub fn with_defaults(defaults: &str, user: ParsedData) -> Result<ParsedData, String> {
+    let base = parse_key_value_config_strict(defaults, &ConfigOptions::default())
+        .map_err(|err| format!("Invalid defaults: {}", err))?;
+    resolve_chain(&[base, user])
+}
+
+/// How [`ParsedData::join`] treats left rows without a matching key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinKind {
+    /// Drop left rows that have no match on the right.
+    Inner,
+    /// Keep every left row, with empty cells for the right-hand columns
+    /// when there is no match.
+    LeftOuter,
+}
+
+impl ParsedData {
+    /// Join two `CsvTable`s on the key column `on`, present in both.
+    ///
+    /// The result has the left headers followed by the right-hand
+    /// non-key headers; a right header that clashes with an earlier one
+    /// gets a `_right` suffix.  Each left row is paired with every matching
+    /// right row, in order.  Cells missing from short rows read as
+    /// empty, and every joined row is padded to the full width.  Rows
+    /// with an empty key match nothing.  If `name_right` is taken too,
+    /// the suffix is numbered: `name_right2`, `name_right3`, ...
+    ///
+    /// Returns `Err` if either side is not a `CsvTable` or lacks `on`.
+    pub fn join(
+        &self,
+        other: &ParsedData,
+        on: &str,
+        kind: JoinKind,
+    ) -> Result<ParsedData, String> {
+        let (lh, lrows, rh, rrows) = match (self, other) {
+            (
+                ParsedData::CsvTable { headers: lh, rows: lrows },
+                ParsedData::CsvTable { headers: rh, rows: rrows },
+            ) => (lh, lrows, rh, rrows),
+            _ => return Err("join is only supported between CsvTables".into()),
+        };
+        let key_col = |headers: &[String], side: &str| {
+            headers
+                .iter()
+                .position(|h| h == on)
+                .ok_or_else(|| format!("Key column {:?} missing from the {} table", on, side))
+        };
+        let lkey = key_col(lh, "left")?;
+        let rkey = key_col(rh, "right")?;
+
+        let right_cols: Vec<usize> = (0..rh.len()).filter(|&c| c != rkey).collect();
+        let mut headers = lh.clone();
+        for &c in &right_cols {
+            let name = &rh[c];
+            let mut unique = name.clone();
+            let mut n = 1;
+            while headers.contains(&unique) {
+                unique = match n {
+                    1 => format!("{}_right", name),
+                    _ => format!("{}_right{}", name, n),
+                };
+                n += 1;
+            }
+            headers.push(unique);
+        }
+
+        fn cell(row: &[String], c: usize) -> &str {
+            row.get(c).map_or("", String::as_str)
+        }
+        let mut by_key: HashMap<&str, Vec<&Vec<String>>> = HashMap::new();
+        for row in rrows.iter().filter(|row| !cell(row, rkey).is_empty()) {
+            by_key.entry(cell(row, rkey)).or_default().push(row);
+        }
+
+        let left_row = |left: &Vec<String>| {
+            let mut row = left.clone();
+            row.resize(lh.len(), String::new());
+            row
+        };
+        let mut rows = Vec::new();
+        for left in lrows {
+            match by_key.get(cell(left, lkey)) {
+                Some(matches) => {
+                    for right in matches {
+                        let mut row = left_row(left);
+                        row.extend(right_cols.iter().map(|&c| cell(right, c).to_string()));
+                        rows.push(row);
+                    }
+                }
+                None if kind == JoinKind::LeftOuter => {
+                    let mut row = left_row(left);
+                    row.resize(headers.len(), String::new());
+                    rows.push(row);
+                }
+                None => {}
+            }
+        }
+
+        Ok(ParsedData::CsvTable { headers, rows })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn config(pairs: &[(&str, &str)]) -> ParsedData {
+        ParsedData::Config(object(pairs))
+    }
+
+    #[test]
+    fn config_becomes_key_value_table() {
+        let mut map = HashMap::new();
+        map.insert("network.port".to_string(), "8080".to_string());
+        map.insert("metadata.name".to_string(), "test_app".to_string());
+
+        match config_to_table(&ParsedData::Config(map)).unwrap() {
+            ParsedData::CsvTable { headers, rows } => {
+                assert_eq!(headers, vec!["key", "value"]);
+                assert_eq!(rows.len(), 2);
+                assert_eq!(rows[0], vec!["metadata.name", "test_app"]);
+            }
+            other => panic!("expected CsvTable, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn chain_later_configs_override_earlier() {
+        let defaults = config(&[("port", "80"), ("timeout", "30"), ("debug", "off")]);
+        let app = config(&[("port", "8080"), ("name", "app")]);
+        let host = config(&[("debug", "on"), ("port", "9090")]);
+
+        let resolved = resolve_chain(&[defaults, app, host]).unwrap();
+        assert_eq!(
+            resolved.entries_sorted(),
+            vec![
+                ("debug", "on"),
+                ("name", "app"),
+                ("port", "9090"),
+                ("timeout", "30"),
+            ]
+        );
+    }
+
+    #[test]
+    fn chain_rejects_non_config() {
+        let table = ParsedData::CsvTable {
+            headers: vec!["a".into()],
+            rows: Vec::new(),
+        };
+        let err = resolve_chain(&[config(&[("a", "1")]), table]).unwrap_err();
+        assert!(err.contains("Input 1"), "{}", err);
+    }
+
+    #[test]
+    fn non_config_is_rejected() {
+        let table = ParsedData::CsvTable {
+            headers: vec!["a".into()],
+            rows: Vec::new(),
+        };
+        assert!(config_to_table(&table).is_err());
+    }
+
+    fn table(headers: &[&str], rows: &[&[&str]]) -> ParsedData {
+        ParsedData::CsvTable {
+            headers: headers.iter().map(|h| h.to_string()).collect(),
+            rows: rows
+                .iter()
+                .map(|r| r.iter().map(|f| f.to_string()).collect())
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn inner_join_on_matching_key() {
+        let users = table(&["id", "name"], &[&["1", "alice"], &["2", "bob"]]);
+        let orders = table(&["id", "name", "total"], &[&["2", "widget", "9"]]);
+        let joined = users.join(&orders, "id", JoinKind::Inner).unwrap();
+        assert_eq!(
+            joined,
+            table(
+                &["id", "name", "name_right", "total"],
+                &[&["2", "bob", "widget", "9"]]
+            )
+        );
+    }
+
+    #[test]
+    fn left_outer_join_fills_missing_matches() {
+        let users = table(&["id", "name"], &[&["1", "alice"], &["2", "bob"]]);
+        let orders = table(&["id", "total"], &[&["2", "9"]]);
+        let joined = users.join(&orders, "id", JoinKind::LeftOuter).unwrap();
+        assert_eq!(
+            joined,
+            table(&["id", "name", "total"], &[&["1", "alice", ""], &["2", "bob", "9"]])
+        );
+        assert!(users.join(&orders, "user_id", JoinKind::Inner).is_err());
+    }
+
+    #[test]
+    fn ragged_rows_join_as_empty_cells() {
+        let left = table(&["a", "id"], &[&["1"], &["2", "k"]]);
+        let right = table(&["id", "x", "y"], &[&["k", "9"], &[""]]);
+        let joined = left.join(&right, "id", JoinKind::LeftOuter).unwrap();
+        assert_eq!(
+            joined,
+            table(&["a", "id", "x", "y"], &[&["1", "", "", ""], &["2", "k", "9", ""]])
+        );
+    }
+
+    #[test]
+    fn clashing_suffixes_stay_unique() {
+        let left = table(&["id", "name", "name_right"], &[&["1", "a", "b"]]);
+        let right = table(&["id", "name"], &[&["1", "c"]]);
+        let joined = left.join(&right, "id", JoinKind::Inner).unwrap();
+        assert_eq!(
+            joined,
+            table(&["id", "name", "name_right", "name_right2"], &[&["1", "a", "b", "c"]])
+        );
+    }
+
+    #[test]
+    fn empty_keys_never_match() {
+        let left = table(&["id", "a"], &[&["", "1"], &["k", "2"]]);
+        let right = table(&["id", "b"], &[&["", "x"], &["k", "y"]]);
+        let inner = left.join(&right, "id", JoinKind::Inner).unwrap();
+        assert_eq!(inner, table(&["id", "a", "b"], &[&["k", "2", "y"]]));
+
+        let outer = left.join(&right, "id", JoinKind::LeftOuter).unwrap();
+        assert_eq!(outer, table(&["id", "a", "b"], &[&["", "1", ""], &["k", "2", "y"]]));
+    }
+
+    #[test]
+    fn json_records_union_their_keys() {
+        let data = ParsedData::JsonRecords(vec![
+            object(&[("id", "1"), ("name", "a")]),
+            object(&[("id", "2"), ("email", "b@x")]),
+        ]);
+        let converted = json_to_table(&data, &TableOptions::default()).unwrap();
+        assert_eq!(
+            converted,
+            table(&["email", "id", "name"], &[&["", "1", "a"], &["b@x", "2", ""]])
+        );
+    }
+
+    #[test]
+    fn strict_keys_rejects_the_first_divergent_object() {
+        let data = ParsedData::JsonRecords(vec![
+            object(&[("id", "1")]),
+            object(&[("id", "2")]),
+            object(&[("id", "3"), ("extra", "x")]),
+        ]);
+        let options = TableOptions {
+            json_strict_keys: true,
+        };
+        let err = json_to_table(&data, &options).unwrap_err();
+        assert!(err.starts_with("Object 2 has keys"), "{}", err);
+    }
+
+    #[test]
+    fn embedded_defaults_fill_in_missing_user_keys() {
+        let defaults = "[network]\nport = 80\ntimeout = 30\n";
+        let user = config(&[("network.port", "8080")]);
+        let resolved = with_defaults(defaults, user).unwrap();
+        assert_eq!(
+            resolved.entries_sorted(),
+            vec![("network.port", "8080"), ("network.timeout", "30")]
+        );
+
+        let err = with_defaults("port 80\n", config(&[])).unwrap_err();
+        assert!(err.starts_with("Invalid defaults: Line 1"), "{}", err);
+    }
+}
@@ -1,4 +1,77 @@
-use crate::processor::ParsedData;
+use crate::error::source_snippet;
+use crate::processor::{ParsedData, Span};
+use crate::text::{self, is_blank};
+use crate::typing::{infer_column_type, parse_number, ColumnType, NumericLocale};
+
+/// Options controlling how CSV content is split into fields and how
+/// cell types are inferred.
+#[derive(Debug, Clone)]
+pub struct CsvOptions {
+    /// Field separator.  May be more than one character (e.g. `::`);
+    /// must not be empty.
+    pub delimiter: String,
+    /// Character used to quote fields that contain the delimiter.  A
+    /// doubled quote character inside a quoted field yields a literal
+    /// quote.
+    pub quote: char,
+    /// Separators used when inferring numeric column types and computing
+    /// column stats.  Stored cell values are never rewritten.
+    pub numeric_locale: NumericLocale,
+    /// Cell values (e.g. `NA`, `NULL`, `-`) that denote a missing value.
+    /// They are treated as empty cells for type inference and stats.
+    pub null_tokens: Vec<String>,
+    /// If set, cells matching a null token are stored as empty strings
+    /// instead of being kept as-is.
+    pub blank_null_tokens: bool,
+    /// If set and [`has_header`] judges the first line to be data, the
+    /// headers `col_0..col_n` are generated and every line is a data
+    /// row.  Otherwise the first line is always the header.
+    pub auto_header: bool,
+    /// [`parse_csv_lenient`] stops scanning once this many bad rows have
+    /// been collected (a limit of 0 is treated as 1).  `None` means no
+    /// limit.  The strict parsers stop at the first bad row regardless.
+    pub max_errors: Option<usize>,
+    /// If set, the first blank line after the header ends the table and
+    /// the rest of the input is ignored, for files that pack several
+    /// tables separated by blank lines.
+    pub stop_at_blank: bool,
+    /// If set, the header row must match these headers exactly, or the
+    /// parse fails listing the missing and unexpected ones.
+    pub expect_headers: Option<Vec<String>>,
+    /// Accept the `expect_headers` in any column order.
+    pub headers_any_order: bool,
+    /// 0-based index of the line holding the header; the lines before it
+    /// (a title, a date, ...) are ignored.  Every line counts towards
+    /// the index, including blank ones.  The first non-blank line from
+    /// there on is the header.
+    pub header_line: usize,
+}
+
+impl CsvOptions {
+    /// Returns `true` if `value` is empty or one of the null tokens.
+    pub fn is_null(&self, value: &str) -> bool {
+        let value = text::trim(value);
+        value.is_empty() || self.null_tokens.iter().any(|t| t == value)
+    }
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            delimiter: ",".into(),
+            quote: '"',
+            numeric_locale: NumericLocale::default(),
+            null_tokens: Vec::new(),
+            blank_null_tokens: false,
+            auto_header: false,
+            max_errors: None,
+            stop_at_blank: false,
+            expect_headers: None,
+            headers_any_order: false,
+            header_line: 0,
+        }
+    }
+}
 
 /// Parse CSV content (comma-separated values with a header row).
 ///
@@ -6,52 +79,725 @@ use crate::processor::ParsedData;
 /// lines are data rows.  Each field is parsed by splitting on commas
 /// and trimming whitespace.
 ///
-/// # Panics
+/// # Errors
 ///
-/// Panics (via `.unwrap()`) if any data row has a different number of
-/// fields than the header.
+/// Returns `Err` if the content is empty or any data row has a
+/// different number of fields than the header.  An empty field between
+/// two delimiters (`a,,c`) is kept as an empty string; a row that is
+/// short of fields is never padded.
 pub fn parse_csv(content: &str) -> Result<ParsedData, String> {
-    let lines: Vec<&str> = content
-        .lines()
-        .map(|l| l.trim())
-        .filter(|l| !l.is_empty())
-        .collect();
+    parse_csv_with(content, &CsvOptions::default())
+}
+
+/// Parse delimited content whose fields are separated by `delimiter`,
+/// which may be several characters long.
+///
+/// A delimiter inside a quoted field is kept as part of the field.
+/// Returns `Err` if `delimiter` is empty.
+pub fn parse_delimited_str(content: &str, delimiter: &str) -> Result<ParsedData, String> {
+    let options = CsvOptions {
+        delimiter: delimiter.to_string(),
+        ..CsvOptions::default()
+    };
+    parse_csv_with(content, &options)
+}
+
+/// Parse CSV content using the given `options`.
+///
+/// Behaves like [`parse_csv`], except that fields are separated by
+/// `options.delimiter` and may be wrapped in `options.quote` to embed
+/// it.
+pub fn parse_csv_with(content: &str, options: &CsvOptions) -> Result<ParsedData, String> {
+    parse_csv_with_spans(content, options).map(|(data, _)| data)
+}
+
+/// Parse CSV content like [`parse_csv_with`], also returning the source
+/// [`Span`] of each data row's (trimmed) line.
+///
+/// `spans[i]` corresponds to `rows[i]` of the returned table.
+pub fn parse_csv_with_spans(
+    content: &str,
+    options: &CsvOptions,
+) -> Result<(ParsedData, Vec<Span>), String> {
+    parse_rows(content, options, OnBadRow::Fail).map(|scan| (scan.data, scan.spans))
+}
+
+/// A `CsvTable` together with which of its data cells were quoted in
+/// the source; see [`parse_csv_with_quoting`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuotedCsv {
+    pub data: ParsedData,
+    /// `quoted[i][j]` is `true` if cell `j` of data row `i` was quoted.
+    pub quoted: Vec<Vec<bool>>,
+}
+
+/// Parse CSV content like [`parse_csv_with`], also recording which
+/// data cells were quoted.
+pub fn parse_csv_with_quoting(content: &str, options: &CsvOptions) -> Result<QuotedCsv, String> {
+    parse_rows(content, options, OnBadRow::Fail).map(|scan| QuotedCsv {
+        data: scan.data,
+        quoted: scan.quoted,
+    })
+}
+
+impl QuotedCsv {
+    /// Headers of the columns whose numeric cells are quoted in some
+    /// rows and bare in others, often a sign of an inconsistent
+    /// upstream writer.  Listed in header order.
+    ///
+    /// Numbers are read with the default [`CsvOptions`]; text and empty
+    /// cells are ignored.
+    pub fn quoting_consistency_report(&self) -> Vec<String> {
+        let ParsedData::CsvTable { headers, rows } = &self.data else {
+            return Vec::new();
+        };
+        let options = CsvOptions::default();
+        headers
+            .iter()
+            .enumerate()
+            .filter(|&(col, _)| {
+                let (mut quoted, mut bare) = (false, false);
+                for (row, flags) in rows.iter().zip(&self.quoted) {
+                    let cell = row.get(col).map_or("", String::as_str);
+                    let numeric = parse_number(cell, options.numeric_locale).is_some();
+                    if !numeric || options.is_null(cell) {
+                        continue;
+                    }
+                    if flags.get(col).copied().unwrap_or(false) {
+                        quoted = true;
+                    } else {
+                        bare = true;
+                    }
+                }
+                quoted && bare
+            })
+            .map(|(_, header)| header.clone())
+            .collect()
+    }
+}
+
+/// Best-effort variant of [`parse_csv_with`]: rows with the wrong number
+/// of fields are skipped and their errors collected instead of failing
+/// the whole parse.
+///
+/// If there is no header row (or the delimiter is empty), the table is
+/// empty and the single error explains why.  With `options.max_errors`
+/// set, rows after the bad row that reaches the limit are not scanned.
+pub fn parse_csv_lenient(content: &str, options: &CsvOptions) -> (ParsedData, Vec<String>) {
+    let (data, errors, _) = scan_csv_lenient(content, options);
+    (data, errors)
+}
+
+/// [`parse_csv_lenient`], also returning `true` if rows were left
+/// unscanned because `options.max_errors` was reached.
+pub(crate) fn scan_csv_lenient(
+    content: &str,
+    options: &CsvOptions,
+) -> (ParsedData, Vec<String>, bool) {
+    let limit = options.max_errors.map(|max| max.max(1));
+    let mut errors = Vec::new();
+    let result = parse_rows(content, options, |err| {
+        errors.push(err);
+        if limit.is_some_and(|max| errors.len() >= max) {
+            OnBadRow::Stop
+        } else {
+            OnBadRow::Skip
+        }
+    });
+    match result {
+        Ok(scan) => (scan.data, errors, scan.truncated),
+        Err(err) => {
+            let empty = ParsedData::CsvTable {
+                headers: Vec::new(),
+                rows: Vec::new(),
+            };
+            (empty, vec![err], false)
+        }
+    }
+}
+
+/// What [`parse_rows`] does after reporting a bad row.
+enum OnBadRow {
+    /// Skip the row and carry on.
+    Skip,
+    /// Skip the row and return the rows parsed so far.
+    Stop,
+    /// Fail the whole parse with this error.
+    Fail(String),
+}
+
+/// Everything [`parse_rows`] learns about the kept data rows.
+struct RowScan {
+    data: ParsedData,
+    /// The source span of each row.
+    spans: Vec<Span>,
+    /// Which cells of each row were quoted.
+    quoted: Vec<Vec<bool>>,
+    /// `true` if `bad_row` stopped the scan before the last row.
+    truncated: bool,
+}
+
+/// Split `content` into a header and data rows.
+///
+/// A row with the wrong number of fields is passed to `bad_row` as an
+/// error message, which decides how to continue.
+fn parse_rows(
+    content: &str,
+    options: &CsvOptions,
+    mut bad_row: impl FnMut(String) -> OnBadRow,
+) -> Result<RowScan, String> {
+    if options.delimiter.is_empty() {
+        return Err("CSV delimiter must not be empty".into());
+    }
+
+    let mut lines: Vec<&str> = Vec::new();
+    for line in content.lines().skip(options.header_line).map(text::trim) {
+        if line.is_empty() {
+            if options.stop_at_blank && !lines.is_empty() {
+                break;
+            }
+            continue;
+        }
+        lines.push(line);
+    }
 
     if lines.is_empty() {
         return Err("Empty CSV content".into());
     }
 
-    let headers: Vec<String> = lines[0]
-        .split(',')
-        .map(|h| h.trim().to_string())
-        .collect();
+    let first_fields = split_fields(lines[0], options);
+    let (headers, first_data) = if options.auto_header && !has_header_with(&lines, options) {
+        let synthesized = (0..first_fields.len()).map(|i| format!("col_{}", i)).collect();
+        (synthesized, 0)
+    } else {
+        (first_fields, 1)
+    };
+
+    check_headers(&headers, options)?;
 
     let num_cols = headers.len();
     let mut rows: Vec<Vec<String>> = Vec::new();
+    let mut spans: Vec<Span> = Vec::new();
+    let mut quoting: Vec<Vec<bool>> = Vec::new();
+    let mut truncated = false;
 
-    for (line_no, &line) in lines[1..].iter().enumerate() {
-        let fields: Vec<String> = line
-            .split(',')
-            .map(|f| f.trim().to_string())
-            .collect();
+    for (line_no, &line) in lines[first_data..].iter().enumerate() {
+        let mut quoted = Vec::new();
+        let mut fields: Vec<String> = split_quoted(line, options, &mut quoted);
+        if options.blank_null_tokens {
+            for field in fields.iter_mut().filter(|f| options.is_null(f)) {
+                field.clear();
+            }
+        }
+
+        let span = Span::of(content, line);
 
         // Validate that every row has exactly the right number of columns.
-        let valid = (fields.len() == num_cols)
-            .then_some(())
-            .ok_or_else(|| {
-                format!(
-                    "Row {} has {} fields, expected {} (line: {:?})",
-                    line_no + 2,
-                    fields.len(),
-                    num_cols,
-                    line
-                )
-            });
-
-        valid.unwrap();
+        if fields.len() != num_cols {
+            match bad_row(format!(
+                "Row {} has {} fields, expected {} (line: {:?})\n{}",
+                first_data + line_no + 1,
+                fields.len(),
+                num_cols,
+                line,
+                source_snippet(content, span.offset)
+            )) {
+                OnBadRow::Skip => continue,
+                OnBadRow::Stop => {
+                    truncated = first_data + line_no + 1 < lines.len();
+                    break;
+                }
+                OnBadRow::Fail(err) => return Err(err),
+            }
+        }
 
         rows.push(fields);
+        spans.push(span);
+        quoting.push(quoted);
     }
 
+    Ok(RowScan {
+        data: ParsedData::CsvTable { headers, rows },
+        spans,
+        quoted: quoting,
+        truncated,
+    })
+}
+
+/// Check `headers` against `options.expect_headers`, if set.
+fn check_headers(headers: &[String], options: &CsvOptions) -> Result<(), String> {
+    let Some(expected) = &options.expect_headers else {
+        return Ok(());
+    };
+    let missing: Vec<&String> = expected.iter().filter(|h| !headers.contains(h)).collect();
+    let unexpected: Vec<&String> = headers.iter().filter(|h| !expected.contains(h)).collect();
+
+    if !missing.is_empty() || !unexpected.is_empty() {
+        return Err(format!(
+            "Header mismatch: missing {:?}, unexpected {:?}",
+            missing, unexpected
+        ));
+    }
+    if !options.headers_any_order && headers != expected.as_slice() {
+        return Err(format!(
+            "Header order mismatch: expected {:?}, got {:?}",
+            expected, headers
+        ));
+    }
+    Ok(())
+}
+
+/// Parse CSV from `lines`, as produced by reading a file a line at a
+/// time, without holding the whole input in memory.
+///
+/// Behaves like [`parse_csv_with`], except that errors carry no source
+/// snippet and `options.auto_header` is ignored, since both need the
+/// whole input.
+pub fn parse_csv_lines<I, S>(lines: I, options: &CsvOptions) -> Result<ParsedData, String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    let headers = scan_csv_lines(lines, options, |row| {
+        rows.push(row);
+        Ok(())
+    })?;
     Ok(ParsedData::CsvTable { headers, rows })
 }
+
+/// The line-at-a-time scan behind [`parse_csv_lines`]: returns the
+/// headers and hands each data row to `on_row` as soon as it is split,
+/// stopping at the first error from either the row or `on_row`.
+pub(crate) fn scan_csv_lines<I, S>(
+    lines: I,
+    options: &CsvOptions,
+    mut on_row: impl FnMut(Vec<String>) -> Result<(), String>,
+) -> Result<Vec<String>, String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    if options.delimiter.is_empty() {
+        return Err("CSV delimiter must not be empty".into());
+    }
+
+    let mut headers: Option<Vec<String>> = None;
+    let mut row_count = 0usize;
+
+    for line in lines.into_iter().skip(options.header_line) {
+        let line = text::trim(line.as_ref());
+        if line.is_empty() {
+            if options.stop_at_blank && headers.is_some() {
+                break;
+            }
+            continue;
+        }
+        let Some(headers) = &headers else {
+            let fields = split_fields(line, options);
+            check_headers(&fields, options)?;
+            headers = Some(fields);
+            continue;
+        };
+
+        let mut fields = split_fields(line, options);
+        if options.blank_null_tokens {
+            for field in fields.iter_mut().filter(|f| options.is_null(f)) {
+                field.clear();
+            }
+        }
+        if fields.len() != headers.len() {
+            return Err(format!(
+                "Row {} has {} fields, expected {} (line: {:?})",
+                row_count + 2,
+                fields.len(),
+                headers.len(),
+                line
+            ));
+        }
+        on_row(fields)?;
+        row_count += 1;
+    }
+
+    headers.ok_or_else(|| "Empty CSV content".into())
+}
+
+/// Parse content holding several CSV tables separated by blank lines,
+/// each with its own header row, into one `CsvTable` per block.
+///
+/// Runs of blank lines count as a single separator, and leading or
+/// trailing blank lines produce no table.  Returns `Err` prefixed with
+/// the 0-based table index if any block fails to parse; row numbers in
+/// the message are relative to that block.
+pub fn parse_multi_csv(content: &str) -> Result<Vec<ParsedData>, String> {
+    let mut blocks: Vec<Vec<&str>> = Vec::new();
+    let mut current = Vec::new();
+    for line in content.lines() {
+        if !text::trim(line).is_empty() {
+            current.push(line);
+        } else if !current.is_empty() {
+            blocks.push(std::mem::take(&mut current));
+        }
+    }
+    blocks.push(current);
+
+    blocks
+        .iter()
+        .filter(|block| !block.is_empty())
+        .enumerate()
+        .map(|(i, block)| {
+            parse_csv(&block.join("\n")).map_err(|err| format!("Table {}: {}", i, err))
+        })
+        .collect()
+}
+
+/// Parse the header and at most `n` data rows of `content`, for
+/// previews of large files.
+///
+/// Lines after the `n`-th data row are never looked at, so a bad row
+/// further down does not fail the preview.  Errors carry no source
+/// snippet, as with [`parse_csv_lines`].
+pub fn parse_csv_preview(content: &str, n: usize) -> Result<ParsedData, String> {
+    let lines = content
+        .lines()
+        .filter(|line| !text::trim(line).is_empty())
+        .take(n.saturating_add(1));
+    parse_csv_lines(lines, &CsvOptions::default())
+}
+
+/// Guess whether the first non-empty line of `content` is a header row.
+///
+/// Each column's first cell is compared with the cells below it:
+///
+/// - a text first cell above a numeric column is strong evidence of a
+///   header, so the result is `true`;
+/// - otherwise a numeric first cell means the first row is data, so the
+///   result is `false`;
+/// - otherwise (everything is text) the first row is assumed to be a
+///   header, matching what [`parse_csv`] does.
+pub fn has_header(content: &str) -> bool {
+    let lines: Vec<&str> = content
+        .lines()
+        .map(text::trim)
+        .filter(|l| !l.is_empty())
+        .collect();
+    has_header_with(&lines, &CsvOptions::default())
+}
+
+/// [`has_header`] over already-trimmed non-empty `lines`, split with
+/// `options`.
+fn has_header_with(lines: &[&str], options: &CsvOptions) -> bool {
+    let rows: Vec<Vec<String>> = lines.iter().map(|l| split_fields(l, options)).collect();
+
+    let Some((first, rest)) = rows.split_first() else {
+        return false;
+    };
+
+    let is_numeric = |v: &str| parse_number(v, options.numeric_locale).is_some();
+
+    let text_over_numbers = first.iter().enumerate().any(|(col, cell)| {
+        let below = rest.iter().filter_map(|r| r.get(col)).map(String::as_str);
+        !is_numeric(cell)
+            && matches!(
+                infer_column_type(below, options),
+                ColumnType::Int | ColumnType::Float
+            )
+    });
+    if text_over_numbers {
+        return true;
+    }
+
+    !first.iter().any(|cell| is_numeric(cell))
+}
+
+/// Split a single CSV line into trimmed fields.
+///
+/// Commas inside a quoted section are kept as part of the field, and a
+/// doubled quote character inside a quoted section produces one literal
+/// quote.  Whitespace outside quotes is trimmed; whitespace inside
+/// quotes is preserved.
+fn split_fields(line: &str, options: &CsvOptions) -> Vec<String> {
+    split_quoted(line, options, &mut Vec::new())
+}
+
+/// Like [`split_fields`], also pushing to `quoted` whether each field
+/// was quoted.
+fn split_quoted(line: &str, options: &CsvOptions, quoted: &mut Vec<bool>) -> Vec<String> {
+    let quote = options.quote;
+    let delimiter = options.delimiter.as_str();
+    let mut fields = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut was_quoted = false;
+    let mut rest = line;
+
+    while let Some(ch) = rest.chars().next() {
+        let after = &rest[ch.len_utf8()..];
+
+        if in_quotes {
+            if ch == quote {
+                if after.starts_with(quote) {
+                    current.push(quote);
+                    rest = &after[quote.len_utf8()..];
+                    continue;
+                }
+                in_quotes = false;
+            } else {
+                current.push(ch);
+            }
+        } else if !delimiter.is_empty() && rest.starts_with(delimiter) {
+            fields.push(finish_field(&mut current, was_quoted));
+            quoted.push(was_quoted);
+            was_quoted = false;
+            rest = &rest[delimiter.len()..];
+            continue;
+        } else if ch == quote {
+            // Drop any whitespace that preceded the opening quote.
+            if text::trim(&current).is_empty() {
+                current.clear();
+            }
+            in_quotes = true;
+            was_quoted = true;
+        } else if was_quoted && is_blank(ch) {
+            // Ignore whitespace between a closing quote and the delimiter.
+        } else {
+            current.push(ch);
+        }
+
+        rest = after;
+    }
+    fields.push(finish_field(&mut current, was_quoted));
+    quoted.push(was_quoted);
+
+    fields
+}
+
+/// Take the accumulated field text, trimming it unless it was quoted.
+fn finish_field(current: &mut String, was_quoted: bool) -> String {
+    let field = std::mem::take(current);
+    if was_quoted {
+        field
+    } else {
+        text::trim(&field).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_field_with_custom_quote_char() {
+        let options = CsvOptions {
+            quote: '\'',
+            ..CsvOptions::default()
+        };
+        let data = parse_csv_with("name,city\n'Smith, J',NYC", &options).unwrap();
+        match data {
+            ParsedData::CsvTable { headers, rows } => {
+                assert_eq!(headers, vec!["name", "city"]);
+                assert_eq!(rows, vec![vec!["Smith, J", "NYC"]]);
+            }
+            other => panic!("expected CsvTable, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn multi_char_delimiter() {
+        let data = parse_delimited_str("id::name\n1::\"a::b\"\n2::c, d", "::").unwrap();
+        match data {
+            ParsedData::CsvTable { headers, rows } => {
+                assert_eq!(headers, vec!["id", "name"]);
+                assert_eq!(rows, vec![vec!["1", "a::b"], vec!["2", "c, d"]]);
+            }
+            other => panic!("expected CsvTable, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn ragged_row_error_includes_source_snippet() {
+        let content = "id,name,score\n1,alpha,10\n2,beta\n3,gamma,30\n";
+        let err = parse_csv(content).unwrap_err();
+        assert!(err.contains("> 3 | 2,beta"), "{}", err);
+        assert!(err.contains("  2 | 1,alpha,10"), "{}", err);
+        assert!(err.contains("  4 | 3,gamma,30"), "{}", err);
+    }
+
+    #[test]
+    fn empty_delimiter_is_rejected() {
+        assert!(parse_delimited_str("a\n1", "").is_err());
+    }
+
+    #[test]
+    fn has_header_detects_text_over_numbers() {
+        assert!(has_header("id,score\n1,9.5\n2,7.25\n"));
+    }
+
+    #[test]
+    fn has_header_is_false_for_numeric_file() {
+        assert!(!has_header("1,9.5\n2,7.25\n3,8\n"));
+    }
+
+    #[test]
+    fn row_spans_point_at_source_lines() {
+        let input = "id,name\n1,alpha\n\n2,beta\n";
+        let (_, spans) = parse_csv_with_spans(input, &CsvOptions::default()).unwrap();
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[1].offset, input.find("2,beta").unwrap());
+        assert_eq!(spans[1].len, "2,beta".len());
+    }
+
+    #[test]
+    fn auto_header_synthesizes_names_for_numeric_file() {
+        let options = CsvOptions {
+            auto_header: true,
+            ..CsvOptions::default()
+        };
+        let data = parse_csv_with("1,9.5\n2,7.25\n3,8\n", &options).unwrap();
+        match &data {
+            ParsedData::CsvTable { headers, rows } => {
+                assert_eq!(headers, &vec!["col_0", "col_1"]);
+                assert_eq!(rows.len(), 3);
+            }
+            other => panic!("expected CsvTable, got {:?}", other),
+        }
+
+        let with_header = parse_csv_with("id,score\n1,9.5\n", &options).unwrap();
+        assert_eq!(with_header.cell(0, "score"), Some("9.5"));
+    }
+
+    #[test]
+    fn empty_middle_field_is_not_a_short_row() {
+        let data = parse_csv("x,y,z\na,,c\n").unwrap();
+        assert_eq!(data.cell(0, "y"), Some(""));
+        assert_eq!(data.cell(0, "z"), Some("c"));
+
+        let err = parse_csv("x,y,z\na,c\n").unwrap_err();
+        assert!(err.starts_with("Row 2 has 2 fields, expected 3"), "{}", err);
+    }
+
+    #[test]
+    fn quoted_empty_and_doubled_quote_fields() {
+        let options = CsvOptions::default();
+        assert_eq!(split_fields(r#"a,"",c"#, &options), vec!["a", "", "c"]);
+        assert_eq!(split_fields("a,,c", &options), vec!["a", "", "c"]);
+        assert_eq!(split_fields(r#"a,"""",c"#, &options), vec!["a", "\"", "c"]);
+        assert_eq!(split_fields(r#"a,"""#, &options), vec!["a", ""]);
+    }
+
+    #[test]
+    fn preview_stops_after_n_rows() {
+        let mut content = String::from("id,name\n");
+        for i in 0..1000 {
+            content.push_str(&format!("{},row{}\n\n", i, i));
+        }
+        content.push_str("not,enough,fields,here\n");
+
+        let preview = parse_csv_preview(&content, 3).unwrap();
+        assert_eq!(preview.len(), 3);
+        assert_eq!(preview.cell(2, "name"), Some("row2"));
+        assert!(parse_csv(&content).is_err());
+    }
+
+    #[test]
+    fn stop_at_blank_parses_only_the_first_section() {
+        let content = "\nid,name\n1,alpha\n2,beta\n\nsku,qty,price\nA1,3,9.99\n";
+        let options = CsvOptions {
+            stop_at_blank: true,
+            ..CsvOptions::default()
+        };
+        let expected = ParsedData::CsvTable {
+            headers: vec!["id".into(), "name".into()],
+            rows: vec![
+                vec!["1".into(), "alpha".into()],
+                vec!["2".into(), "beta".into()],
+            ],
+        };
+        assert_eq!(parse_csv_with(content, &options), Ok(expected.clone()));
+        assert_eq!(parse_csv_lines(content.lines(), &options), Ok(expected));
+        assert!(parse_csv(content).is_err());
+    }
+
+    #[test]
+    fn multi_csv_splits_on_blank_lines() {
+        let content = "id,name\n1,alpha\n2,beta\n\n\nsku,qty\nA1,3\n\n";
+        let tables = parse_multi_csv(content).unwrap();
+        assert_eq!(tables.len(), 2);
+        match (&tables[0], &tables[1]) {
+            (
+                ParsedData::CsvTable { headers: first, rows: first_rows },
+                ParsedData::CsvTable { headers: second, rows: second_rows },
+            ) => {
+                assert_eq!(first, &vec!["id", "name"]);
+                assert_eq!(first_rows.len(), 2);
+                assert_eq!(second, &vec!["sku", "qty"]);
+                assert_eq!(second_rows, &vec![vec!["A1", "3"]]);
+            }
+            other => panic!("expected two CsvTables, got {:?}", other),
+        }
+
+        let err = parse_multi_csv("a,b\n1,2\n\nc,d\n3\n").unwrap_err();
+        assert!(err.starts_with("Table 1: Row 2 has 1 fields"), "{}", err);
+    }
+
+    fn expecting(headers: &[&str], any_order: bool) -> CsvOptions {
+        CsvOptions {
+            expect_headers: Some(headers.iter().map(|h| h.to_string()).collect()),
+            headers_any_order: any_order,
+            ..CsvOptions::default()
+        }
+    }
+
+    #[test]
+    fn expected_headers_match() {
+        let content = "id,name\n1,alpha\n";
+        assert!(parse_csv_with(content, &expecting(&["id", "name"], false)).is_ok());
+        assert!(parse_csv_with(content, &expecting(&["name", "id"], true)).is_ok());
+
+        let err = parse_csv_with(content, &expecting(&["name", "id"], false)).unwrap_err();
+        assert!(err.starts_with("Header order mismatch"), "{}", err);
+    }
+
+    #[test]
+    fn expected_headers_report_a_missing_column() {
+        let err = parse_csv_with("id\n1\n", &expecting(&["id", "name"], true)).unwrap_err();
+        assert_eq!(err, "Header mismatch: missing [\"name\"], unexpected []");
+    }
+
+    #[test]
+    fn expected_headers_report_an_extra_column() {
+        let options = expecting(&["id"], true);
+        let err = parse_csv_with("id,email\n1,a@x\n", &options).unwrap_err();
+        assert_eq!(err, "Header mismatch: missing [], unexpected [\"email\"]");
+        let lines = ["id,email", "1,a@x"];
+        assert_eq!(parse_csv_lines(lines, &options), Err(err));
+    }
+
+    #[test]
+    fn header_line_skips_the_preamble() {
+        let content = "Quarterly report\nGenerated 2024-01-01\nid,name\n1,alpha\n\n2,beta\n";
+        let options = CsvOptions {
+            header_line: 2,
+            ..CsvOptions::default()
+        };
+        let expected = ParsedData::CsvTable {
+            headers: vec!["id".into(), "name".into()],
+            rows: vec![
+                vec!["1".into(), "alpha".into()],
+                vec!["2".into(), "beta".into()],
+            ],
+        };
+        assert_eq!(parse_csv_with(content, &options), Ok(expected.clone()));
+        assert_eq!(parse_csv_lines(content.lines(), &options), Ok(expected));
+    }
+
+    #[test]
+    fn mixed_quoting_of_numbers_is_flagged() {
+        let content = "id,price,name\n1,\"9.50\",\"a\"\n2,12,b\n\"3\",\"4\",\"c\"\n";
+        let parsed = parse_csv_with_quoting(content, &CsvOptions::default()).unwrap();
+        assert_eq!(parsed.quoted[0], vec![false, true, true]);
+        assert_eq!(parsed.data.cell(0, "price"), Some("9.50"));
+        // `name` mixes quoting too, but holds no numbers.
+        assert_eq!(parsed.quoting_consistency_report(), vec!["id", "price"]);
+    }
+}
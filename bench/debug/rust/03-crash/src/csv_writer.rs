@@ -0,0 +1,74 @@
+use crate::processor::ParsedData;
+use crate::text::{self, LineEnding};
+
+/// Serialise a `CsvTable` as comma-separated text with a header row,
+/// every line (including the last) ended by `line_ending`.
+///
+/// Fields containing a comma, a quote, a line break or surrounding
+/// whitespace are quoted, with quotes doubled.  Line breaks inside a
+/// quoted field are written as `line_ending` too.  Returns `Err` for
+/// other variants.
+///
+/// The crate's CSV parser reads one line per record, so output with a
+/// line break inside a field does not round-trip through
+/// [`parse_csv`](crate::csv_parser::parse_csv).
+pub fn to_csv(data: &ParsedData, line_ending: LineEnding) -> Result<String, String> {
+    let (headers, rows) = match data {
+        ParsedData::CsvTable { headers, rows } => (headers, rows),
+        _ => return Err("to_csv expects a CsvTable".into()),
+    };
+
+    let mut out = String::new();
+    for line in std::iter::once(headers).chain(rows) {
+        let fields: Vec<String> = line.iter().map(|f| quote_field(f, line_ending)).collect();
+        out.push_str(&fields.join(","));
+        out.push_str(line_ending.as_str());
+    }
+    Ok(out)
+}
+
+/// Quote `field` if the CSV parser would otherwise split or trim it.
+fn quote_field(field: &str, line_ending: LineEnding) -> String {
+    let needs_quotes = field.contains([',', '"', '\n', '\r']) || text::trim(field) != field;
+    if !needs_quotes {
+        return field.to_string();
+    }
+    format!("\"{}\"", line_ending.normalize(&field.replace('"', "\"\"")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csv_parser::parse_csv;
+
+    #[test]
+    fn simple_table_round_trips() {
+        let table = parse_csv("id,name\n1,\"alpha, beta\"\n2,\"say \"\"hi\"\"\"\n").unwrap();
+        let csv = to_csv(&table, LineEnding::Lf).unwrap();
+        assert_eq!(csv, "id,name\n1,\"alpha, beta\"\n2,\"say \"\"hi\"\"\"\n");
+        assert_eq!(parse_csv(&csv).unwrap(), table);
+    }
+
+    #[test]
+    fn crlf_is_used_for_rows_and_embedded_newlines() {
+        let table = ParsedData::CsvTable {
+            headers: vec!["id".into(), "note".into()],
+            rows: vec![vec!["1".into(), "line one\nline two".into()]],
+        };
+        assert_eq!(
+            to_csv(&table, LineEnding::CrLf).unwrap(),
+            "id,note\r\n1,\"line one\r\nline two\"\r\n"
+        );
+        assert!(to_csv(&ParsedData::JsonRecords(Vec::new()), LineEnding::Lf).is_err());
+    }
+
+    #[test]
+    fn embedded_newlines_do_not_round_trip() {
+        let table = ParsedData::CsvTable {
+            headers: vec!["id".into(), "note".into()],
+            rows: vec![vec!["1".into(), "line one\nline two".into()]],
+        };
+        // The parser is line-based: `line two"` reads as a short record.
+        assert!(parse_csv(&to_csv(&table, LineEnding::Lf).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,119 @@
+use std::fmt;
+
+/// Errors returned by [`crate::parser::parse`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// Content detected as CSV could not be parsed.
+    Csv(String),
+    /// Content detected as YAML-style config could not be parsed.
+    Yaml(String),
+    /// Content read as JSON could not be parsed.
+    Json(String),
+    /// Reading the input failed.
+    Io(String),
+    /// Byte input is not valid UTF-8; `offset` is the position of the
+    /// first invalid byte.
+    InvalidUtf8 { offset: usize },
+    /// A line of key-value config was neither a section header nor a
+    /// `key = value` pair.
+    Config(String),
+    /// Content was detected as JSON, JSON parsing failed, and parsing it
+    /// as CSV instead also failed.
+    FallbackFailed { json_err: String, csv_err: String },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Csv(err) => write!(f, "CSV parse failed: {}", err),
+            ParseError::Yaml(err) => write!(f, "YAML parse failed: {}", err),
+            ParseError::Json(err) => write!(f, "JSON parse failed: {}", err),
+            ParseError::Io(err) => write!(f, "read failed: {}", err),
+            ParseError::InvalidUtf8 { offset } => {
+                write!(f, "input is not valid UTF-8 (first bad byte at offset {})", offset)
+            }
+            ParseError::Config(err) => write!(f, "config parse failed: {}", err),
+            ParseError::FallbackFailed { json_err, csv_err } => write!(
+                f,
+                "content looked like JSON but failed to parse ({}); \
+                 parsing it as CSV also failed ({})",
+                json_err, csv_err
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl ParseError {
+    /// Process exit code for this error, so scripts driving the CLI can
+    /// branch on the kind of failure:
+    ///
+    /// | Code | Meaning                            | Variants                        |
+    /// |------|------------------------------------|---------------------------------|
+    /// | 2    | Malformed input in a known format  | `Csv`, `Yaml`, `Json`, `Config` |
+    /// | 3    | Reading the input failed           | `Io`                            |
+    /// | 4    | The format could not be determined | `FallbackFailed`                |
+    /// | 5    | The input is not UTF-8 text        | `InvalidUtf8`                   |
+    ///
+    /// Code 1 is left for failures other than a `ParseError`.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ParseError::Csv(_)
+            | ParseError::Yaml(_)
+            | ParseError::Json(_)
+            | ParseError::Config(_) => 2,
+            ParseError::Io(_) => 3,
+            ParseError::FallbackFailed { .. } => 4,
+            ParseError::InvalidUtf8 { .. } => 5,
+        }
+    }
+}
+
+/// Render the source line containing byte `offset` of `content`, with
+/// one line of context before and after, for use in error messages.
+///
+/// Lines are numbered from 1 and the failing line is marked with `>`:
+///
+/// ```text
+///   2 | 1,alpha,10
+/// > 3 | 2,beta
+///   4 | 3,gamma,30
+/// ```
+pub fn source_snippet(content: &str, offset: usize) -> String {
+    let failing = content[..offset.min(content.len())].matches('\n').count();
+    let first = failing.saturating_sub(1);
+
+    content
+        .lines()
+        .enumerate()
+        .skip(first)
+        .take(failing + 2 - first)
+        .map(|(i, line)| {
+            let marker = if i == failing { '>' } else { ' ' };
+            format!("{} {} | {}", marker, i + 1, line)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snippet_marks_the_failing_line_with_context() {
+        let content = "a\nb\nc\nd\n";
+        assert_eq!(source_snippet(content, 4), "  2 | b\n> 3 | c\n  4 | d");
+        assert_eq!(source_snippet(content, 0), "> 1 | a\n  2 | b");
+    }
+
+    #[test]
+    fn error_kinds_map_to_distinct_exit_codes() {
+        let err = crate::parser::parse("a:\n  b:\n    c: 1\n").unwrap_err();
+        assert!(matches!(err, ParseError::Yaml(_)), "{:?}", err);
+        assert_eq!(err.exit_code(), 2);
+        assert_eq!(ParseError::Io("gone".into()).exit_code(), 3);
+        assert_eq!(ParseError::InvalidUtf8 { offset: 0 }.exit_code(), 5);
+    }
+}
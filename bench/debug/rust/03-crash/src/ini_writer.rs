@@ -0,0 +1,91 @@
+use std::collections::BTreeMap;
+
+use crate::processor::ParsedData;
+use crate::text::LineEnding;
+
+/// Serialise a `Config` as INI-style `key = value` lines, readable by
+/// [`crate::parser::parse_key_value_config_with`] with the default `.`
+/// separator, every line ended by `line_ending`.
+///
+/// Output that starts with a `[section]` header is not reliably read
+/// back by [`crate::parser::parse`], whose format detection takes a
+/// leading `[` for JSON.
+///
+/// Keys without a `.` are written first; the rest are grouped under a
+/// `[section]` header named after the part before the first `.`, with
+/// sections and keys sorted.  Returns `Err` for other variants.
+pub fn to_ini(data: &ParsedData, line_ending: LineEnding) -> Result<String, String> {
+    if !matches!(data, ParsedData::Config(_)) {
+        return Err("to_ini expects a Config".into());
+    }
+    let newline = line_ending.as_str();
+
+    let mut top_level = Vec::new();
+    let mut sections: BTreeMap<&str, Vec<(&str, &str)>> = BTreeMap::new();
+    for (key, value) in data.entries_sorted() {
+        match key.split_once('.') {
+            Some((section, key)) => sections.entry(section).or_default().push((key, value)),
+            None => top_level.push((key, value)),
+        }
+    }
+
+    let mut out = String::new();
+    for (key, value) in &top_level {
+        out.push_str(&format!("{} = {}{}", key, value, newline));
+    }
+    for (i, (section, entries)) in sections.iter().enumerate() {
+        if i > 0 || !top_level.is_empty() {
+            out.push_str(newline);
+        }
+        out.push_str(&format!("[{}]{}", section, newline));
+        for (key, value) in entries {
+            out.push_str(&format!("{} = {}{}", key, value, newline));
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse, parse_key_value_config_with, ConfigOptions};
+
+    fn config(pairs: &[(&str, &str)]) -> ParsedData {
+        ParsedData::Config(
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn sections_are_grouped_and_round_trip() {
+        let data = config(&[
+            ("name", "app"),
+            ("network.port", "8080"),
+            ("network.host", "localhost"),
+            ("metadata.owner", "ops"),
+        ]);
+        let ini = to_ini(&data, LineEnding::Lf).unwrap();
+        assert_eq!(
+            ini,
+            "name = app\n\n[metadata]\nowner = ops\n\n[network]\nhost = localhost\nport = 8080\n"
+        );
+        assert_eq!(parse(&ini).unwrap(), data);
+    }
+
+    #[test]
+    fn sections_only_config_round_trips() {
+        let data = config(&[("db.host", "x"), ("db.port", "5432"), ("cache.ttl", "60")]);
+        let ini = to_ini(&data, LineEnding::Lf).unwrap();
+        assert_eq!(ini, "[cache]\nttl = 60\n\n[db]\nhost = x\nport = 5432\n");
+        assert_eq!(parse_key_value_config_with(&ini, &ConfigOptions::default()), data);
+    }
+
+    #[test]
+    fn crlf_line_endings() {
+        let data = config(&[("a.b", "1")]);
+        assert_eq!(to_ini(&data, LineEnding::CrLf).unwrap(), "[a]\r\nb = 1\r\n");
+    }
+}
@@ -13,6 +13,9 @@ use crate::processor::ParsedData;
 /// ]
 /// ```
 ///
+/// Objects are split on top-level commas, so braces and commas inside
+/// strings are kept.  `[]` yields no records and `{}` an empty one.
+///
 /// Returns `Err` if the content is not valid simplified-JSON.
 pub fn parse_json(content: &str) -> Result<ParsedData, String> {
     let trimmed = content.trim();
@@ -22,72 +25,370 @@ pub fn parse_json(content: &str) -> Result<ParsedData, String> {
     }
 
     let inner = &trimmed[1..trimmed.len() - 1];
+    if inner.trim().is_empty() {
+        return Ok(ParsedData::JsonRecords(Vec::new()));
+    }
+
     let mut records: Vec<HashMap<String, String>> = Vec::new();
+    for chunk in split_top_level(inner) {
+        if chunk.trim().is_empty() {
+            return Err(format!("Invalid JSON array: {}", trimmed));
+        }
+        records.push(parse_object(chunk)?);
+    }
 
-    // Rough split on '},' to get individual objects.
-    for chunk in split_objects(inner) {
-        let chunk = chunk.trim().trim_matches(|c| c == '{' || c == '}');
-        if chunk.is_empty() {
+    Ok(ParsedData::JsonRecords(records))
+}
+
+/// Parse one `{"key": "value", ...}` object into a flat map.
+///
+/// Pairs are split on top-level commas, and each pair on its first
+/// `:` outside a string, so commas and colons inside strings are
+/// preserved.  Nested values are kept as their raw text.
+fn parse_object(chunk: &str) -> Result<HashMap<String, String>, String> {
+    let chunk = chunk.trim();
+    let chunk = chunk
+        .strip_prefix('{')
+        .and_then(|c| c.strip_suffix('}'))
+        .unwrap_or(chunk);
+
+    let mut map = HashMap::new();
+    for pair in split_top_level(chunk) {
+        let pair = pair.trim();
+        if pair.is_empty() {
             continue;
         }
-        let mut map = HashMap::new();
-        for pair in chunk.split(',') {
-            let pair = pair.trim();
-            if pair.is_empty() {
-                continue;
-            }
-            let parts: Vec<&str> = pair.splitn(2, ':').collect();
-            if parts.len() != 2 {
-                return Err(format!("Invalid JSON pair: {}", pair));
+        let Some((key, raw)) = split_pair(pair) else {
+            return Err(format!("Invalid JSON pair: {}", pair));
+        };
+        let key = key.trim();
+        let key = match key.strip_prefix('"') {
+            Some(_) => unescape(key)?,
+            None => key.to_string(),
+        };
+        let raw = raw.trim();
+        if !is_quoted_or_nested(raw)
+            && !matches!(raw, "true" | "false" | "null")
+            && parse_json_number(raw).is_none()
+        {
+            return Err(format!("Invalid JSON value: {}", raw));
+        }
+        // Numbers keep their source text, so `1` and `1.0` stay distinct
+        // and integers too large for `i64` are not rounded.
+        let val = if raw.starts_with('"') {
+            unescape(raw)?
+        } else {
+            raw.to_string()
+        };
+        map.insert(key, val);
+    }
+    Ok(map)
+}
+
+/// Decode the JSON string literal `raw`, quotes included, resolving its
+/// escape sequences.
+fn unescape(raw: &str) -> Result<String, String> {
+    let invalid = || format!("Invalid JSON string: {}", raw);
+    let inner = raw
+        .strip_prefix('"')
+        .and_then(|r| r.strip_suffix('"'))
+        .filter(|_| raw.len() >= 2)
+        .ok_or_else(invalid)?;
+
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        match chars.next().ok_or_else(invalid)? {
+            '"' => out.push('"'),
+            '\\' => out.push('\\'),
+            '/' => out.push('/'),
+            'n' => out.push('\n'),
+            't' => out.push('\t'),
+            'r' => out.push('\r'),
+            'b' => out.push('\u{8}'),
+            'f' => out.push('\u{c}'),
+            'u' => {
+                let hex: String = chars.by_ref().take(4).collect();
+                if hex.len() != 4 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(invalid());
+                }
+                let code = u32::from_str_radix(&hex, 16).map_err(|_| invalid())?;
+                out.push(char::from_u32(code).ok_or_else(invalid)?);
             }
-            let key = parts[0].trim().trim_matches('"').to_string();
-            let val = parts[1].trim().trim_matches('"').to_string();
-            map.insert(key, val);
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(out)
+}
+
+/// Returns `true` if a raw value is a string, object or array.
+fn is_quoted_or_nested(raw: &str) -> bool {
+    raw.starts_with(['"', '{', '['])
+}
+
+/// A JSON number, keeping integers and floats apart.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonNumber {
+    /// An integer that fits in `i64`.
+    Int(i64),
+    /// A number with a fraction or exponent.
+    Float(f64),
+    /// An integer outside the `i64` range, kept as its source digits.
+    BigInt(String),
+}
+
+/// Parse `raw` as a number following the strict JSON grammar: no
+/// leading `+`, no leading zeros, and digits on both sides of a `.`.
+pub fn parse_json_number(raw: &str) -> Option<JsonNumber> {
+    let digits = |s: &str| s.len() - s.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+
+    let unsigned = raw.strip_prefix('-').unwrap_or(raw);
+    let int_len = digits(unsigned);
+    if int_len == 0 || (int_len > 1 && unsigned.starts_with('0')) {
+        return None;
+    }
+    let mut rest = &unsigned[int_len..];
+    let is_int = rest.is_empty();
+
+    if let Some(frac) = rest.strip_prefix('.') {
+        let frac_len = digits(frac);
+        if frac_len == 0 {
+            return None;
         }
-        if !map.is_empty() {
-            records.push(map);
+        rest = &frac[frac_len..];
+    }
+    if let Some(exp) = rest.strip_prefix(['e', 'E']) {
+        let exp = exp.strip_prefix(['+', '-']).unwrap_or(exp);
+        let exp_len = digits(exp);
+        if exp_len == 0 {
+            return None;
         }
+        rest = &exp[exp_len..];
+    }
+    if !rest.is_empty() {
+        return None;
     }
 
-    if records.is_empty() {
-        return Err("JSON array contained no objects".into());
+    if is_int {
+        Some(match raw.parse::<i64>() {
+            Ok(n) => JsonNumber::Int(n),
+            Err(_) => JsonNumber::BigInt(raw.to_string()),
+        })
+    } else {
+        raw.parse::<f64>().ok().map(JsonNumber::Float)
     }
+}
 
-    Ok(ParsedData::JsonRecords(records))
+/// Split `s` on commas that are outside strings and nested
+/// braces/brackets.
+fn split_top_level(s: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut scanner = Scanner::default();
+    let mut start = 0;
+
+    for (i, ch) in s.char_indices() {
+        if ch == ',' && scanner.at_top_level() {
+            parts.push(&s[start..i]);
+            start = i + 1;
+        } else {
+            scanner.feed(ch);
+        }
+    }
+    parts.push(&s[start..]);
+    parts
 }
 
-/// Split the inner content of a JSON array into individual object strings.
-fn split_objects(inner: &str) -> Vec<String> {
-    let mut objects = Vec::new();
-    let mut depth = 0i32;
-    let mut current = String::new();
+/// Split a `key: value` pair at its first `:` outside a string.
+fn split_pair(pair: &str) -> Option<(&str, &str)> {
+    let mut scanner = Scanner::default();
+    for (i, ch) in pair.char_indices() {
+        if ch == ':' && scanner.at_top_level() {
+            return Some((&pair[..i], &pair[i + 1..]));
+        }
+        scanner.feed(ch);
+    }
+    None
+}
 
-    for ch in inner.chars() {
-        match ch {
-            '{' => {
-                depth += 1;
-                current.push(ch);
-            }
-            '}' => {
-                depth -= 1;
-                current.push(ch);
-                if depth == 0 {
-                    objects.push(current.clone());
-                    current.clear();
-                }
+/// Tracks string and nesting state while scanning JSON text.
+#[derive(Default)]
+struct Scanner {
+    depth: i32,
+    in_string: bool,
+    escaped: bool,
+}
+
+impl Scanner {
+    fn at_top_level(&self) -> bool {
+        self.depth == 0 && !self.in_string
+    }
+
+    fn feed(&mut self, ch: char) {
+        if self.in_string {
+            if self.escaped {
+                self.escaped = false;
+            } else if ch == '\\' {
+                self.escaped = true;
+            } else if ch == '"' {
+                self.in_string = false;
             }
-            ',' if depth == 0 => {
-                // skip commas between objects
+            return;
+        }
+        match ch {
+            '"' => self.in_string = true,
+            '{' | '[' => self.depth += 1,
+            '}' | ']' => self.depth -= 1,
+            _ => {}
+        }
+    }
+}
+
+/// Iterator over the objects of a JSON array, yielding one object at a
+/// time as a flat row instead of building the whole record list.
+///
+/// Element boundaries are found by tracking strings and nested
+/// braces/brackets, so `}` or `,` inside a string does not split an
+/// element.  Created by [`stream_json_array`].
+pub struct JsonArrayStream<'a> {
+    rest: &'a str,
+    failed: bool,
+}
+
+/// Stream the objects of the JSON array in `content`.
+///
+/// The first item is an `Err` if `content` is not a JSON array,
+/// including when it is empty.  Empty objects yield empty rows.
+pub fn stream_json_array(content: &str) -> JsonArrayStream<'_> {
+    let trimmed = content.trim();
+    match trimmed.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+        Some(inner) => JsonArrayStream {
+            rest: inner,
+            failed: false,
+        },
+        None => JsonArrayStream {
+            rest: trimmed,
+            failed: true,
+        },
+    }
+}
+
+impl<'a> Iterator for JsonArrayStream<'a> {
+    type Item = Result<HashMap<String, String>, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            self.failed = false;
+            self.rest = "";
+            return Some(Err("Not a JSON array".into()));
+        }
+
+        let rest = self.rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        if rest.is_empty() {
+            self.rest = rest;
+            return None;
+        }
+
+        let mut scanner = Scanner::default();
+        let mut end = rest.len();
+        for (i, ch) in rest.char_indices() {
+            if ch == ',' && scanner.at_top_level() {
+                end = i;
+                break;
             }
-            _ => {
-                current.push(ch);
+            scanner.feed(ch);
+        }
+
+        let element = &rest[..end];
+        self.rest = &rest[end..];
+        Some(parse_object(element))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn streams_one_row_per_object() {
+        let input = r#"[
+            {"id": "1", "note": "has } brace"},
+            {"id": "2", "tags": ["a", "b"], "meta": {"k": "v"}},
+            {"id": "3", "note": "comma, inside"}
+        ]"#;
+        let rows: Vec<_> = stream_json_array(input).collect::<Result<_, _>>().unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0]["note"], "has } brace");
+        assert_eq!(rows[2]["note"], "comma, inside");
+    }
+
+    #[test]
+    fn stream_rejects_non_array() {
+        let mut stream = stream_json_array("{\"id\": 1}");
+        assert!(stream.next().unwrap().is_err());
+        assert!(stream.next().is_none());
+    }
+
+    #[test]
+    fn unicode_escapes_need_four_hex_digits() {
+        assert_eq!(unescape(r#""\u0041\u00e9""#).unwrap(), "Aé");
+        for raw in [r#""\u41""#, r#""\u+041""#, r#""\u004""#, r#""\u00g1""#] {
+            assert!(unescape(raw).is_err(), "{}", raw);
+        }
+    }
+
+    #[test]
+    fn stream_rejects_empty_input() {
+        for input in ["", "  \n\t"] {
+            let mut stream = stream_json_array(input);
+            assert!(stream.next().unwrap().is_err(), "{input:?}");
+            assert!(stream.next().is_none());
+        }
+    }
+
+    #[test]
+    fn stream_keeps_empty_objects() {
+        let rows: Vec<_> = stream_json_array(r#"[{"id": "1"}, {}, {"id": "3"}]"#)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(rows.len(), 3);
+        assert!(rows[1].is_empty());
+        assert_eq!(rows[2]["id"], "3");
+    }
+
+    #[test]
+    fn integers_and_floats_are_distinguished() {
+        assert_eq!(parse_json_number("42"), Some(JsonNumber::Int(42)));
+        assert_eq!(parse_json_number("-1.5e3"), Some(JsonNumber::Float(-1500.0)));
+        assert_eq!(parse_json_number("01"), None);
+        assert_eq!(parse_json_number("1."), None);
+
+        match parse_json(r#"[{"a": 1, "b": 1.0}]"#).unwrap() {
+            ParsedData::JsonRecords(records) => {
+                assert_eq!(records[0]["a"], "1");
+                assert_eq!(records[0]["b"], "1.0");
             }
+            other => panic!("expected JsonRecords, got {:?}", other),
         }
     }
 
-    if !current.trim().is_empty() {
-        objects.push(current);
+    #[test]
+    fn oversized_integer_keeps_its_digits() {
+        let big = "123456789012345678901234567890";
+        assert_eq!(parse_json_number(big), Some(JsonNumber::BigInt(big.to_string())));
+
+        let input = format!(r#"[{{"id": {}}}]"#, big);
+        match parse_json(&input).unwrap() {
+            ParsedData::JsonRecords(records) => assert_eq!(records[0]["id"], big),
+            other => panic!("expected JsonRecords, got {:?}", other),
+        }
     }
 
-    objects
+    #[test]
+    fn bare_words_are_rejected() {
+        assert!(parse_json(r#"[{"a": abc}]"#).is_err());
+    }
 }
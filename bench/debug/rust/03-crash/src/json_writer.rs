@@ -0,0 +1,160 @@
+use crate::processor::ParsedData;
+use crate::text::LineEnding;
+
+/// Serialise `data` as a compact JSON array of objects, readable by
+/// [`crate::json_parser::parse_json`].
+///
+/// Each JSON record or table row becomes one object, with table columns
+/// in header order.  A `Config` becomes a single object.  Keys of
+/// map-backed objects are written in sorted order so the output is
+/// stable.
+pub fn to_json(data: &ParsedData) -> String {
+    write_json(data, None)
+}
+
+/// Serialise `data` like [`to_json`], but with one member per line,
+/// ended by `line_ending`, and `indent` spaces per nesting level.
+pub fn to_json_pretty(data: &ParsedData, indent: usize, line_ending: LineEnding) -> String {
+    write_json(data, Some((indent, line_ending)))
+}
+
+fn write_json(data: &ParsedData, layout: Option<(usize, LineEnding)>) -> String {
+    let objects: Vec<Vec<(&str, &str)>> = match data {
+        ParsedData::Config(_) => vec![data.entries_sorted()],
+        ParsedData::JsonRecords(records) => records
+            .iter()
+            .map(|record| {
+                let mut pairs: Vec<(&str, &str)> = record
+                    .iter()
+                    .map(|(k, v)| (k.as_str(), v.as_str()))
+                    .collect();
+                pairs.sort_unstable();
+                pairs
+            })
+            .collect(),
+        ParsedData::CsvTable { headers, rows } => rows
+            .iter()
+            .map(|row| {
+                headers
+                    .iter()
+                    .map(String::as_str)
+                    .zip(row.iter().map(String::as_str))
+                    .collect()
+            })
+            .collect(),
+    };
+
+    let (newline, pad, colon) = match layout {
+        Some((n, line_ending)) => (line_ending.as_str(), " ".repeat(n), ": "),
+        None => ("", String::new(), ":"),
+    };
+
+    let mut out = String::from("[");
+    for (i, pairs) in objects.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push_str(newline);
+        out.push_str(&pad);
+        out.push('{');
+        for (j, (key, value)) in pairs.iter().enumerate() {
+            if j > 0 {
+                out.push(',');
+            }
+            out.push_str(newline);
+            out.push_str(&pad);
+            out.push_str(&pad);
+            out.push_str(&format!("{}{}{}", quote(key), colon, quote(value)));
+        }
+        if !pairs.is_empty() {
+            out.push_str(newline);
+            out.push_str(&pad);
+        }
+        out.push('}');
+    }
+    if !objects.is_empty() {
+        out.push_str(newline);
+    }
+    out.push(']');
+    out
+}
+
+/// Quote `s` as a JSON string.
+fn quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::csv_parser::parse_csv;
+    use crate::json_parser::parse_json;
+
+    #[test]
+    fn pretty_output_reparses_like_compact() {
+        let table = parse_csv("id,name\n1,alpha\n2,beta gamma\n").unwrap();
+        let compact = to_json(&table);
+        let pretty = to_json_pretty(&table, 2, LineEnding::Lf);
+
+        assert_eq!(
+            compact,
+            r#"[{"id":"1","name":"alpha"},{"id":"2","name":"beta gamma"}]"#
+        );
+        assert!(
+            pretty.starts_with("[\n  {\n    \"id\": \"1\",\n"),
+            "{}",
+            pretty
+        );
+        assert_eq!(parse_json(&pretty).unwrap(), parse_json(&compact).unwrap());
+    }
+
+    #[test]
+    fn pretty_output_can_use_crlf() {
+        let table = parse_csv("id\n1\n").unwrap();
+        assert_eq!(
+            to_json_pretty(&table, 1, LineEnding::CrLf),
+            "[\r\n {\r\n  \"id\": \"1\"\r\n }\r\n]"
+        );
+    }
+
+    #[test]
+    fn escaped_quotes_and_backslashes_reparse() {
+        let mut record = HashMap::new();
+        record.insert("k".to_string(), r#"say "hi" to C:\temp\"#.to_string());
+        record.insert("tab\t".to_string(), "line\nbreak".to_string());
+        let data = ParsedData::JsonRecords(vec![record]);
+
+        let json = to_json(&data);
+        assert!(json.contains(r#""say \"hi\" to C:\\temp\\""#), "{}", json);
+        assert_eq!(parse_json(&json).unwrap(), data);
+    }
+
+    #[test]
+    fn structural_characters_and_empty_records_reparse() {
+        let mut record = HashMap::new();
+        record.insert("k:1".to_string(), "a}b{".to_string());
+        let data = ParsedData::JsonRecords(vec![record, HashMap::new()]);
+
+        let json = to_json(&data);
+        assert_eq!(parse_json(&json).unwrap(), data);
+        let pretty = to_json_pretty(&data, 2, LineEnding::Lf);
+        assert_eq!(parse_json(&pretty).unwrap(), data);
+
+        let empty = ParsedData::JsonRecords(Vec::new());
+        assert_eq!(parse_json(&to_json(&empty)).unwrap(), empty);
+    }
+}
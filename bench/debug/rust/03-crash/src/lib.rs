@@ -0,0 +1,18 @@
+//! Multi-format parser: detects JSON, CSV, INI-style or YAML-style config
+//! content and parses it into a [`processor::ParsedData`].
+
+pub mod cache;
+pub mod convert;
+pub mod csv_parser;
+pub mod csv_writer;
+pub mod error;
+pub mod ini_writer;
+pub mod json_parser;
+pub mod json_writer;
+pub mod parser;
+pub mod processor;
+pub mod schema;
+pub mod spill;
+pub mod text;
+pub mod typing;
+pub mod yaml_parser;
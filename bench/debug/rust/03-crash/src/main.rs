@@ -1,9 +1,5 @@
-mod processor;
-mod parser;
-mod json_parser;
-mod csv_parser;
-
-use processor::summarise;
+use parser_bench::parser;
+use parser_bench::processor::summarise;
 
 /// Sample INI-style config input.
 const INPUT: &str = "\
@@ -18,6 +14,11 @@ timeout = 30
 ";
 
 fn main() {
-    let data = parser::parse(INPUT);
-    summarise(&data);
+    match parser::parse(INPUT) {
+        Ok(data) => summarise(&data),
+        Err(err) => {
+            eprintln!("ERROR: {}", err);
+            std::process::exit(err.exit_code());
+        }
+    }
 }
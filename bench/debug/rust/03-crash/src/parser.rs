@@ -1,8 +1,67 @@
-use crate::csv_parser;
+use crate::csv_parser::{self, CsvOptions};
+use crate::error::{source_snippet, ParseError};
 use crate::json_parser;
-use crate::processor::ParsedData;
+use crate::yaml_parser;
+use crate::processor::{ParsedData, Span, Summary};
+use crate::text;
 
 use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read};
+
+/// Options for parsing INI-style key-value config.
+#[derive(Debug, Clone)]
+pub struct ConfigOptions {
+    /// Joins a section name and a key into the stored key, e.g. `.` for
+    /// `network.port`.  Pick another (such as `/` or `::`) if keys
+    /// themselves contain dots.
+    pub section_separator: String,
+    /// [`parse_lenient_with`] and [`parse_key_value_config_lenient`]
+    /// stop scanning once this many malformed lines have been found (a
+    /// limit of 0 is treated as 1).  `None` means no limit.
+    pub max_errors: Option<usize>,
+    /// Fully-qualified keys whose values are kept verbatim: everything
+    /// after the `=`, untrimmed, e.g. for ASCII-art banners or SQL.
+    pub verbatim_keys: Vec<String>,
+    /// Sections all of whose values are kept verbatim.
+    pub verbatim_sections: Vec<String>,
+    /// Treat the file as flat: a `[section]` header is reported as a
+    /// malformed line and does not prefix the keys after it.
+    pub flat: bool,
+    /// Allowlist of section names.  A `[section]` header not in it is
+    /// reported as a malformed line naming the section (see
+    /// [`parse_key_value_config_lenient`]); its keys are still read.
+    /// `None` allows any section.
+    pub known_sections: Option<Vec<String>>,
+    /// Warn about every value that had trailing whitespace before it was
+    /// trimmed, e.g. a path with a stray space (see
+    /// [`LenientConfig::warnings`]).  The trimmed value is still stored;
+    /// warnings do not count toward `max_errors` or fail strict parsing.
+    /// Verbatim values are exempt.
+    pub flag_trailing_whitespace: bool,
+}
+
+impl ConfigOptions {
+    /// Returns `true` if the value of `key` in `section` is kept
+    /// verbatim.
+    fn is_verbatim(&self, section: &str, full_key: &str) -> bool {
+        self.verbatim_sections.iter().any(|s| s == section)
+            || self.verbatim_keys.iter().any(|k| k == full_key)
+    }
+}
+
+impl Default for ConfigOptions {
+    fn default() -> Self {
+        ConfigOptions {
+            section_separator: ".".into(),
+            max_errors: None,
+            verbatim_keys: Vec::new(),
+            verbatim_sections: Vec::new(),
+            flat: false,
+            known_sections: None,
+            flag_trailing_whitespace: false,
+        }
+    }
+}
 
 /// Supported input formats.
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -10,92 +69,458 @@ pub enum Format {
     Json,
     Csv,
     KeyValueConfig,
+    /// YAML-style `key:` blocks with indented `subkey: value` lines.
+    YamlLite,
 }
 
-/// Detect the format of `content` by inspecting its first non-blank line.
+/// How many data lines [`detect_format`] samples.
+const DETECT_SAMPLE_LINES: usize = 20;
+
+/// Detect the format of `content` by inspecting its first non-blank,
+/// non-comment (`#` or `;`) lines.  Content with no such line is
+/// treated as key-value config.
 ///
-/// Heuristics:
+/// Heuristics, on the first data line unless noted:
 /// - Starts with `[`  -> JSON array
-/// - Contains a comma on the first data line -> CSV
+/// - Ends with `:` and contains no `=` -> YAML-style config
+/// - Contains a comma -> CSV, unless most of the first
+///   `DETECT_SAMPLE_LINES` data lines are `key = value` pairs (an `=`
+///   before any comma), as in a config whose first value is a list
+/// - No sampled line contains a comma, `=` or `:`, and there are at
+///   least two -> single-column CSV
 /// - Otherwise        -> key-value config
 pub fn detect_format(content: &str) -> Format {
-    let first_line = content
+    let sample: Vec<&str> = content
         .lines()
-        .map(|l| l.trim())
-        .find(|l| !l.is_empty())
-        .unwrap_or("");
+        .map(text::trim)
+        .filter(|l| !l.is_empty() && !is_comment(l))
+        .take(DETECT_SAMPLE_LINES)
+        .collect();
+    let first_line = sample.first().copied().unwrap_or("");
 
     if first_line.starts_with('[') {
         return Format::Json;
     }
 
+    if first_line.ends_with(':') && !first_line.contains('=') {
+        return Format::YamlLite;
+    }
+
     if first_line.contains(',') {
+        let key_value = sample.iter().filter(|l| is_key_value_line(l)).count();
+        if key_value * 2 > sample.len() {
+            return Format::KeyValueConfig;
+        }
+        return Format::Csv;
+    }
+
+    let has_separator = |l: &&str| l.contains(['=', ':', ',']);
+    if sample.len() >= 2 && !sample.iter().any(has_separator) {
         return Format::Csv;
     }
 
     Format::KeyValueConfig
 }
 
+/// Returns `true` if `line` has an `=` before any comma, i.e. reads as
+/// `key = value` rather than as delimited fields.
+fn is_key_value_line(line: &str) -> bool {
+    match (line.find('='), line.find(',')) {
+        (Some(eq), Some(comma)) => eq < comma,
+        (Some(_), None) => true,
+        (None, _) => false,
+    }
+}
+
+/// Returns `true` for INI-style comment lines.
+fn is_comment(line: &str) -> bool {
+    line.starts_with('#') || line.starts_with(';')
+}
+
 /// Route content to the appropriate parser.
-pub fn parse(content: &str) -> ParsedData {
+///
+/// If content detected as JSON fails to parse, it is retried as CSV;
+/// if that fails too, [`ParseError::FallbackFailed`] carries both
+/// errors.
+pub fn parse(content: &str) -> Result<ParsedData, ParseError> {
     let format = detect_format(content);
 
     match format {
         Format::Json => {
             match json_parser::parse_json(content) {
-                Ok(data) => data,
-                Err(_) => {
+                Ok(data) => Ok(data),
+                Err(json_err) => {
                     // JSON parse failed — fall through to CSV as a guess.
-                    csv_parser::parse_csv(content).expect("CSV parse also failed")
+                    csv_parser::parse_csv(content)
+                        .map_err(|csv_err| ParseError::FallbackFailed { json_err, csv_err })
                 }
             }
         }
         Format::Csv => {
-            csv_parser::parse_csv(content).expect("CSV parse failed")
+            csv_parser::parse_csv(content).map_err(ParseError::Csv)
         }
         Format::KeyValueConfig => {
-            parse_key_value_config(content)
+            Ok(parse_key_value_config(content))
+        }
+        Format::YamlLite => {
+            yaml_parser::parse_yaml_lite(content).map_err(ParseError::Yaml)
         }
     }
 }
 
+/// Best-effort variant of [`parse`] that returns whatever parsed
+/// successfully together with the errors for the parts that did not.
+///
+/// Bad CSV rows are skipped, as are key-value config lines that are not
+/// `key = value` pairs (which [`parse`] silently ignores).  JSON and
+/// YAML-style content is still all-or-nothing: on failure the data is
+/// an empty `Config` and the error is the one [`parse`] returns.
+pub fn parse_lenient(content: &str) -> (ParsedData, Vec<ParseError>) {
+    let result = parse_lenient_with(content, None);
+    (result.data, result.errors)
+}
+
+/// Result of [`parse_lenient_with`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct LenientParse {
+    pub data: ParsedData,
+    pub errors: Vec<ParseError>,
+    /// `true` if scanning stopped at `max_errors` with input left, so
+    /// later parts of it were neither parsed nor checked.
+    pub truncated: bool,
+}
+
+/// Like [`parse_lenient`], but gives up once `max_errors` bad CSV rows
+/// or malformed config lines have been collected, bounding the work
+/// spent on hopeless inputs.
+pub fn parse_lenient_with(content: &str, max_errors: Option<usize>) -> LenientParse {
+    let (data, errors, truncated) = match detect_format(content) {
+        Format::Csv => {
+            let options = CsvOptions {
+                max_errors,
+                ..CsvOptions::default()
+            };
+            let (data, errors, truncated) = csv_parser::scan_csv_lenient(content, &options);
+            (data, errors.into_iter().map(ParseError::Csv).collect(), truncated)
+        }
+        Format::KeyValueConfig => {
+            let options = ConfigOptions {
+                max_errors,
+                ..ConfigOptions::default()
+            };
+            let scan = scan_key_value_config(content, &options);
+            let errors = scan.errors.into_iter().map(ParseError::Config).collect();
+            (scan.data, errors, scan.truncated)
+        }
+        Format::Json | Format::YamlLite => match parse(content) {
+            Ok(data) => (data, Vec::new(), false),
+            Err(err) => (ParsedData::Config(HashMap::new()), vec![err], false),
+        },
+    };
+    LenientParse {
+        data,
+        errors,
+        truncated,
+    }
+}
+
+/// Parse `reader` as `format` without first reading it into a string.
+///
+/// CSV and key-value config are parsed a line at a time (CSV error
+/// messages then carry no source snippet, and `auto_header` is not
+/// applied).  JSON and YAML-style content is read in full and parsed
+/// as usual.  Unlike [`parse`], JSON that fails to parse is not retried
+/// as CSV.
+pub fn parse_reader<R: Read>(reader: R, format: Format) -> Result<ParsedData, ParseError> {
+    let mut reader = BufReader::new(reader);
+    let io_err = |err: io::Error| ParseError::Io(err.to_string());
+
+    match format {
+        Format::Csv | Format::KeyValueConfig => {
+            let mut failure = None;
+            let lines = reader
+                .lines()
+                .map_while(|line| line.map_err(|err| failure = Some(err)).ok());
+            let data = match format {
+                Format::Csv => csv_parser::parse_csv_lines(lines, &CsvOptions::default())
+                    .map_err(ParseError::Csv),
+                _ => Ok(scan_config_lines(lines, None, &ConfigOptions::default()).data),
+            };
+            match failure {
+                Some(err) => Err(io_err(err)),
+                None => data,
+            }
+        }
+        Format::Json | Format::YamlLite => {
+            let mut content = String::new();
+            reader.read_to_string(&mut content).map_err(io_err)?;
+            match format {
+                Format::Json => json_parser::parse_json(&content).map_err(ParseError::Json),
+                _ => yaml_parser::parse_yaml_lite(&content).map_err(ParseError::Yaml),
+            }
+        }
+    }
+}
+
+/// Parse raw bytes as `format`.
+///
+/// A leading UTF-8 byte-order mark is stripped.  Input that is not
+/// valid UTF-8 (e.g. a Latin-1 file) is rejected with
+/// [`ParseError::InvalidUtf8`].  Like [`parse_reader`], JSON that fails
+/// to parse is not retried as CSV.
+pub fn parse_bytes(data: &[u8], format: Format) -> Result<ParsedData, ParseError> {
+    let (bom, data) = match data.strip_prefix(b"\xEF\xBB\xBF") {
+        Some(rest) => (3, rest),
+        None => (0, data),
+    };
+    let content = std::str::from_utf8(data).map_err(|err| ParseError::InvalidUtf8 {
+        offset: bom + err.valid_up_to(),
+    })?;
+
+    match format {
+        Format::Json => json_parser::parse_json(content).map_err(ParseError::Json),
+        Format::Csv => csv_parser::parse_csv(content).map_err(ParseError::Csv),
+        Format::KeyValueConfig => Ok(parse_key_value_config(content)),
+        Format::YamlLite => yaml_parser::parse_yaml_lite(content).map_err(ParseError::Yaml),
+    }
+}
+
+/// Parse `content` like [`parse`], also returning a [`Summary`] of how
+/// many lines were blank, comments, or data.
+///
+/// Comment lines are those the detected format's parser skips: `#` and
+/// `;` for key-value config, `#` for YAML-style config, and none for
+/// CSV or JSON.
+pub fn parse_with_summary(content: &str) -> Result<(ParsedData, Summary), ParseError> {
+    let data = parse(content)?;
+    let mut summary = match detect_format(content) {
+        Format::KeyValueConfig => Summary::count_lines(content, is_comment),
+        Format::YamlLite => Summary::count_lines(content, |l| l.starts_with('#')),
+        Format::Json | Format::Csv => Summary::count_lines(content, |_| false),
+    };
+    summary.entries = data.len();
+    Ok((data, summary))
+}
+
 /// Parse an INI-style key-value configuration file.
 ///
 /// Supports `[section]` headers.  Keys within a section are stored as
 /// `section.key` in the resulting map.
 fn parse_key_value_config(content: &str) -> ParsedData {
+    parse_key_value_config_with_spans(content).0
+}
+
+/// Parse an INI-style key-value configuration file, also recording the
+/// source [`Span`] of each key's (trimmed) line.
+///
+/// The span map is keyed by the same fully-qualified `section.key`
+/// names as the returned `Config`.  If a key repeats, the span of the
+/// last occurrence wins, matching the stored value.
+pub fn parse_key_value_config_with_spans(content: &str) -> (ParsedData, HashMap<String, Span>) {
+    let scan = scan_key_value_config(content, &ConfigOptions::default());
+    (scan.data, scan.spans)
+}
+
+/// Parse an INI-style key-value configuration file, also capturing the
+/// comment lines immediately preceding each key, so a writer can emit
+/// them again.
+///
+/// Comment text is stored without its `#`/`;` marker, one entry per
+/// line.  A blank line or section header between a comment and a key
+/// detaches the comment.  Keys without comments are absent from the
+/// map; as with spans, the last occurrence of a repeated key wins.
+pub fn parse_key_value_config_with_comments(
+    content: &str,
+) -> (ParsedData, HashMap<String, Vec<String>>) {
+    let scan = scan_key_value_config(content, &ConfigOptions::default());
+    (scan.data, scan.comments)
+}
+
+/// Parse an INI-style key-value configuration file, joining section and
+/// key names with `options.section_separator`.
+///
+/// Look keys up with [`ParsedData::get`] and [`ParsedData::section`],
+/// passing the same separator.
+pub fn parse_key_value_config_with(content: &str, options: &ConfigOptions) -> ParsedData {
+    scan_key_value_config(content, options).data
+}
+
+/// Result of [`parse_key_value_config_lenient`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct LenientConfig {
+    pub data: ParsedData,
+    /// One message per malformed line, e.g. an unknown section.
+    pub errors: Vec<String>,
+    /// One message per value flagged by
+    /// `options.flag_trailing_whitespace`.
+    pub warnings: Vec<String>,
+    /// `true` if scanning stopped at `max_errors` with input left.
+    pub truncated: bool,
+}
+
+/// Like [`parse_key_value_config_with`], but also returns the errors
+/// for malformed lines, which are skipped as usual, and any warnings.
+/// Scanning stops early at `options.max_errors`.
+pub fn parse_key_value_config_lenient(content: &str, options: &ConfigOptions) -> LenientConfig {
+    let scan = scan_key_value_config(content, options);
+    LenientConfig {
+        data: scan.data,
+        errors: scan.errors,
+        warnings: scan.warnings,
+        truncated: scan.truncated,
+    }
+}
+
+/// Like [`parse_key_value_config_with`], but returns `Err` with the
+/// first malformed line instead of skipping it.  Combine with
+/// `options.flat` to reject stray section headers in flat files.
+pub fn parse_key_value_config_strict(
+    content: &str,
+    options: &ConfigOptions,
+) -> Result<ParsedData, String> {
+    let options = ConfigOptions {
+        max_errors: Some(1),
+        ..options.clone()
+    };
+    let scan = scan_key_value_config(content, &options);
+    match scan.errors.into_iter().next() {
+        Some(error) => Err(error),
+        None => Ok(scan.data),
+    }
+}
+
+/// Everything [`scan_key_value_config`] learns about a config file.
+struct ConfigScan {
+    data: ParsedData,
+    spans: HashMap<String, Span>,
+    comments: HashMap<String, Vec<String>>,
+    /// One message per malformed line.
+    errors: Vec<String>,
+    /// One message per flagged value.
+    warnings: Vec<String>,
+    /// `true` if `options.max_errors` stopped the scan before the last
+    /// key, section or malformed line.
+    truncated: bool,
+}
+
+/// Shared implementation of the key-value config parsers.
+fn scan_key_value_config(content: &str, options: &ConfigOptions) -> ConfigScan {
+    scan_config_lines(content.lines(), Some(content), options)
+}
+
+/// Scan config `lines`.  If they were split from `source`, spans and
+/// error snippets point into it; otherwise no spans are recorded.
+fn scan_config_lines<I, S>(lines: I, source: Option<&str>, options: &ConfigOptions) -> ConfigScan
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
     let mut map = HashMap::new();
+    let mut spans = HashMap::new();
+    let mut comments = HashMap::new();
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
     let mut current_section = String::new();
+    let mut pending_comments: Vec<String> = Vec::new();
+    let mut stopped = false;
+    let mut truncated = false;
 
-    for line in content.lines() {
-        let line = line.trim();
+    for (line_no, line) in lines.into_iter().enumerate() {
+        let raw = line.as_ref();
+        let line = text::trim(raw);
 
-        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+        if line.is_empty() {
+            pending_comments.clear();
             continue;
         }
+        if is_comment(line) {
+            pending_comments.push(text::trim(&line[1..]).to_string());
+            continue;
+        }
+        if stopped {
+            truncated = true;
+            break;
+        }
+
+        let locate = |message: String| {
+            let mut located = format!("Line {}: {}", line_no + 1, message);
+            if let Some(content) = source {
+                located.push('\n');
+                located.push_str(&source_snippet(content, Span::of(content, line).offset));
+            }
+            located
+        };
+        let mut report = |message: String| {
+            errors.push(locate(message));
+            stopped = options.max_errors.is_some_and(|max| errors.len() >= max.max(1));
+        };
 
         // Section header: [name]
         if line.starts_with('[') && line.ends_with(']') {
-            current_section = line[1..line.len() - 1].trim().to_string();
+            pending_comments.clear();
+            if options.flat {
+                report(format!("section header {:?} in a flat config", line));
+                continue;
+            }
+            current_section = text::trim(&line[1..line.len() - 1]).to_string();
+            let known = options
+                .known_sections
+                .as_ref()
+                .is_none_or(|known| known.contains(&current_section));
+            if !known {
+                report(format!("unknown section {:?}", current_section));
+            }
             continue;
         }
 
         // Key = value pair
         if let Some(eq_pos) = line.find('=') {
-            let key = line[..eq_pos].trim();
-            let value = line[eq_pos + 1..].trim();
+            let key = text::trim(&line[..eq_pos]);
+            let value = text::trim(&line[eq_pos + 1..]);
 
             let full_key = if current_section.is_empty() {
                 key.to_string()
             } else {
-                format!("{}.{}", current_section, key)
+                format!("{}{}{}", current_section, options.section_separator, key)
+            };
+
+            let verbatim = options.is_verbatim(&current_section, &full_key);
+            let value = if verbatim {
+                // Only leading blanks precede the key, so the first `=`
+                // of the raw line is the separator.
+                raw.split_once('=').map_or(value, |(_, after)| after)
+            } else {
+                value
             };
+            let flagged =
+                options.flag_trailing_whitespace && !verbatim && raw.ends_with(text::is_blank);
+            let trailing =
+                flagged.then(|| format!("value of {:?} has trailing whitespace", full_key));
 
+            if let Some(content) = source {
+                spans.insert(full_key.clone(), Span::of(content, line));
+            }
+            if pending_comments.is_empty() {
+                comments.remove(&full_key);
+            } else {
+                comments.insert(full_key.clone(), std::mem::take(&mut pending_comments));
+            }
             map.insert(full_key, value.to_string());
+            warnings.extend(trailing.map(locate));
+        } else {
+            pending_comments.clear();
+            report(format!("expected `key = value`, got {:?}", line));
         }
     }
 
-    ParsedData::Config(map)
+    ConfigScan {
+        data: ParsedData::Config(map),
+        spans,
+        comments,
+        errors,
+        warnings,
+        truncated,
+    }
 }
 
 #[cfg(test)]
@@ -115,4 +540,328 @@ mod tests {
         );
     }
 
+    #[test]
+    fn detect_yaml_lite() {
+        assert_eq!(
+            detect_format("network:\n  port: 8080\n"),
+            Format::YamlLite
+        );
+    }
+
+    #[test]
+    fn detect_ini_is_not_yaml_lite() {
+        assert_eq!(
+            detect_format("port = 8080\ntimeout = 30\n"),
+            Format::KeyValueConfig
+        );
+        assert_eq!(detect_format("label = note:\n"), Format::KeyValueConfig);
+    }
+
+    #[test]
+    fn config_spans_point_at_key_lines() {
+        let input = "[network]\nport = 8080\n  timeout = 30\n";
+        let (_, spans) = parse_key_value_config_with_spans(input);
+        let span = spans["network.timeout"];
+        assert_eq!(span.offset, input.find("timeout").unwrap());
+        assert_eq!(&input[span.offset..span.offset + span.len], "timeout = 30");
+    }
+
+    #[test]
+    fn json_then_csv_failure_reports_both_errors() {
+        // Detected as JSON (leading `[`), not valid JSON, and ragged as CSV.
+        let input = "[section]\nkey = a, b\n";
+        match parse(input) {
+            Err(ParseError::FallbackFailed { json_err, csv_err }) => {
+                assert_eq!(json_err, "Not a JSON array");
+                assert!(csv_err.contains("Row 2 has 2 fields, expected 1"), "{}", csv_err);
+            }
+            other => panic!("expected FallbackFailed, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn summary_counts_blank_comment_and_data_lines() {
+        let input =
+            "# app config\n\nname = app\n; port below\nport = 8080\n\u{200b}\ntimeout = 30\n";
+        let (_, summary) = parse_with_summary(input).unwrap();
+        assert_eq!(
+            summary,
+            Summary {
+                entries: 3,
+                blank_lines: 2,
+                comment_lines: 2,
+                data_lines: 3,
+            }
+        );
+    }
+
+    #[test]
+    fn comments_only_file_is_empty_config() {
+        let input = "# generated, do not edit\n; another comment\n\n";
+        assert_eq!(detect_format(input), Format::KeyValueConfig);
+        match parse(input).unwrap() {
+            ParsedData::Config(map) => assert!(map.is_empty()),
+            other => panic!("expected Config, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn section_headers_only_file_is_empty_config() {
+        match parse_key_value_config("[metadata]\n\n[network]\n") {
+            ParsedData::Config(map) => assert!(map.is_empty()),
+            other => panic!("expected Config, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn non_breaking_space_is_trimmed_from_keys() {
+        match parse("\u{00A0}name = app\nport =\u{FEFF} 8080\n").unwrap() {
+            ParsedData::Config(map) => {
+                assert_eq!(map["name"], "app");
+                assert_eq!(map["port"], "8080");
+            }
+            other => panic!("expected Config, got {:?}", other),
+        }
+        let table = csv_parser::parse_csv("\u{00A0}id,name\n1,\u{00A0}alpha\n").unwrap();
+        assert_eq!(table.cell(0, "name"), Some("alpha"));
+    }
+
+    #[test]
+    fn lenient_csv_keeps_good_rows() {
+        let (data, errors) = parse_lenient("id,name\n1,alpha\n2\n3,gamma\n");
+        assert_eq!(data.len(), 2);
+        assert_eq!(data.cell(1, "name"), Some("gamma"));
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(&errors[0], ParseError::Csv(e) if e.starts_with("Row 3")), "{:?}", errors);
+    }
+
+    #[test]
+    fn lenient_config_reports_malformed_line() {
+        let (data, errors) = parse_lenient("host = localhost\nthis line is junk\nport = 8080\n");
+        assert_eq!(data.len(), 2);
+        assert_eq!(errors.len(), 1);
+        match &errors[0] {
+            ParseError::Config(err) => assert!(err.starts_with("Line 2: expected"), "{}", err),
+            other => panic!("expected a Config error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn custom_section_separator() {
+        let options = ConfigOptions {
+            section_separator: "/".into(),
+            ..ConfigOptions::default()
+        };
+        let input = "name = app\n[network]\nport = 8080\nhost.v4 = 10.0.0.1\n";
+        let data = parse_key_value_config_with(input, &options);
+        assert_eq!(data.get("network/port"), Some("8080"));
+        assert_eq!(data.get("network.port"), None);
+        assert_eq!(
+            data.section("network", "/"),
+            vec![("host.v4", "10.0.0.1"), ("port", "8080")]
+        );
+    }
+
+    #[test]
+    fn leading_comments_are_attached_to_keys() {
+        let input = "# listen port\n; must be > 1024\nport = 8080\n\n# orphaned\n\nhost = x\n";
+        let (data, comments) = parse_key_value_config_with_comments(input);
+        assert_eq!(data.len(), 2);
+        assert_eq!(comments["port"], vec!["listen port", "must be > 1024"]);
+        assert!(!comments.contains_key("host"));
+    }
+
+    #[test]
+    fn lenient_parse_stops_at_max_errors() {
+        let mut input = String::from("id,name\n");
+        for i in 0..20 {
+            input.push_str(&format!("{},ok\n{}\n", i, i));
+        }
+        let result = parse_lenient_with(&input, Some(3));
+        assert_eq!(result.errors.len(), 3);
+        assert!(result.truncated);
+        assert_eq!(result.data.len(), 3);
+
+        let complete = parse_lenient_with(&input, Some(100));
+        assert_eq!(complete.errors.len(), 20);
+        assert!(!complete.truncated);
+
+        let exact = parse_lenient_with(&input, Some(20));
+        assert_eq!(exact.errors.len(), 20);
+        assert!(!exact.truncated);
+    }
+
+    #[test]
+    fn lenient_parse_is_truncated_only_when_input_is_left() {
+        let config = "bad one
+port = 1
+bad two
+";
+        assert!(!parse_lenient_with(config, Some(2)).truncated);
+        let cut = parse_lenient_with(config, Some(1));
+        assert!(cut.truncated);
+        assert_eq!(cut.data.len(), 0);
+
+        let json = parse_lenient_with("[{\"a\": 1},\n{\"b\"}]", Some(1));
+        assert!(matches!(json.errors[..], [ParseError::FallbackFailed { .. }]));
+        assert!(!json.truncated);
+    }
+
+    #[test]
+    fn parse_reader_reads_csv_from_a_cursor() {
+        let bytes = b"id,name\n1,alpha\n\n2,beta\n".to_vec();
+        let data = parse_reader(std::io::Cursor::new(bytes), Format::Csv).unwrap();
+        assert_eq!(data.len(), 2);
+        assert_eq!(data.cell(1, "name"), Some("beta"));
+
+        let err = parse_reader(&b"id,name\n1\n"[..], Format::Csv).unwrap_err();
+        assert!(matches!(err, ParseError::Csv(e) if e.starts_with("Row 2 has 1 fields")));
+    }
+
+    #[test]
+    fn parse_reader_reads_config() {
+        let data = parse_reader(&b"[network]\nport = 8080\n"[..], Format::KeyValueConfig).unwrap();
+        assert_eq!(data.get("network.port"), Some("8080"));
+    }
+
+    #[test]
+    fn inline_list_values() {
+        let data = parse_key_value_config("[network]\nallowed_hosts = alpha, beta\nnone =\n");
+        assert_eq!(
+            data.get_list("network.allowed_hosts"),
+            Some(vec!["alpha".to_string(), "beta".to_string()])
+        );
+        assert_eq!(data.get("network.allowed_hosts"), Some("alpha, beta"));
+        assert_eq!(data.get_list("network.none"), Some(Vec::new()));
+        assert_eq!(data.get_list("network.missing"), None);
+    }
+
+    #[test]
+    fn parse_bytes_strips_bom_and_rejects_invalid_utf8() {
+        let data = parse_bytes(b"\xEF\xBB\xBFid,name\n1,alpha\n", Format::Csv).unwrap();
+        assert_eq!(data.cell(0, "id"), Some("1"));
+
+        // "café" in Latin-1: 0xE9 does not start a valid UTF-8 sequence.
+        let latin1 = b"name = caf\xE9\n";
+        assert_eq!(
+            parse_bytes(latin1, Format::KeyValueConfig),
+            Err(ParseError::InvalidUtf8 { offset: 10 })
+        );
+
+        // Offsets count from the start of the input, BOM included.
+        assert_eq!(
+            parse_bytes(b"\xEF\xBB\xBFa = \xff\n", Format::KeyValueConfig),
+            Err(ParseError::InvalidUtf8 { offset: 7 })
+        );
+    }
+
+    #[test]
+    fn config_whose_first_value_has_a_comma_is_detected_as_config() {
+        let content = "hosts = alpha, beta\nport = 8080\ntimeout = 30\n";
+        assert_eq!(detect_format(content), Format::KeyValueConfig);
+        let data = parse(content).unwrap();
+        assert_eq!(data.get("hosts"), Some("alpha, beta"));
+        assert_eq!(data.get("port"), Some("8080"));
+
+        assert_eq!(detect_format("id,name\n1,a=b\n2,c\n"), Format::Csv);
+    }
+
+    #[test]
+    fn single_column_csv_is_detected_as_csv() {
+        let content = "name\nalpha\nbeta\n";
+        assert_eq!(detect_format(content), Format::Csv);
+        let data = parse(content).unwrap();
+        assert_eq!(data.len(), 2);
+        assert_eq!(data.cell(1, "name"), Some("beta"));
+
+        assert_eq!(detect_format("name = app\n"), Format::KeyValueConfig);
+        assert_eq!(detect_format("standalone\n"), Format::KeyValueConfig);
+    }
+
+    #[test]
+    fn verbatim_keys_keep_their_whitespace() {
+        let content = "name =  app  \nbanner = ##  ##  \n[sql]\nquery =   SELECT 1  \n";
+        let options = ConfigOptions {
+            verbatim_keys: vec!["banner".into()],
+            verbatim_sections: vec!["sql".into()],
+            ..ConfigOptions::default()
+        };
+        let data = parse_key_value_config_with(content, &options);
+        assert_eq!(data.get("name"), Some("app"));
+        assert_eq!(data.get("banner"), Some(" ##  ##  "));
+        assert_eq!(data.get("sql.query"), Some("   SELECT 1  "));
+    }
+
+    #[test]
+    fn flat_configs_reject_section_headers() {
+        let content = "name = app\n[network]\nport = 8080\n";
+        let options = ConfigOptions {
+            flat: true,
+            ..ConfigOptions::default()
+        };
+        let err = parse_key_value_config_strict(content, &options).unwrap_err();
+        assert!(err.starts_with("Line 2: section header \"[network]\""), "{}", err);
+
+        let data = parse_key_value_config_strict(content, &ConfigOptions::default()).unwrap();
+        assert_eq!(data.get("network.port"), Some("8080"));
+        let lenient = parse_key_value_config_with(content, &options);
+        assert_eq!(lenient.get("port"), Some("8080"));
+    }
+
+    #[test]
+    fn sections_outside_the_allowlist_are_reported() {
+        let content = "[server]\nport = 8080\n[sever]\nhost = example.com\n";
+        let options = ConfigOptions {
+            known_sections: Some(vec!["server".into()]),
+            ..ConfigOptions::default()
+        };
+        let err = parse_key_value_config_strict(content, &options).unwrap_err();
+        assert!(err.starts_with("Line 3: unknown section \"sever\""), "{}", err);
+
+        let result = parse_key_value_config_lenient(content, &options);
+        assert_eq!(result.errors.len(), 1);
+        assert!(result.errors[0].starts_with("Line 3: unknown section"), "{}", result.errors[0]);
+        assert_eq!(result.data.get("sever.host"), Some("example.com"));
+        assert_eq!(result.data.get("server.port"), Some("8080"));
+    }
+
+    #[test]
+    fn trailing_whitespace_in_values_is_reported() {
+        let content = "[paths]\nroot = /srv/app \nlogs = /var/log\n";
+        let options = ConfigOptions {
+            flag_trailing_whitespace: true,
+            ..ConfigOptions::default()
+        };
+        let result = parse_key_value_config_lenient(content, &options);
+        assert!(result.errors.is_empty());
+        assert_eq!(result.warnings.len(), 1);
+        assert!(
+            result.warnings[0]
+                .starts_with("Line 2: value of \"paths.root\" has trailing whitespace"),
+            "{}",
+            result.warnings[0]
+        );
+        assert_eq!(result.data.get("paths.root"), Some("/srv/app"));
+        assert_eq!(result.data.get("paths.logs"), Some("/var/log"));
+
+        let unflagged = parse_key_value_config_lenient(content, &ConfigOptions::default());
+        assert!(unflagged.warnings.is_empty());
+    }
+
+    #[test]
+    fn trailing_whitespace_warnings_do_not_fail_or_truncate() {
+        let content = "a = 1 \nb = 2 \nc = 3\n";
+        let options = ConfigOptions {
+            flag_trailing_whitespace: true,
+            max_errors: Some(1),
+            ..ConfigOptions::default()
+        };
+        let result = parse_key_value_config_lenient(content, &options);
+        assert_eq!(result.warnings.len(), 2);
+        assert!(!result.truncated);
+        assert_eq!(result.data.get("c"), Some("3"));
+
+        let data = parse_key_value_config_strict(content, &options).unwrap();
+        assert_eq!(data.get("b"), Some("2"));
+    }
 }
@@ -1,7 +1,11 @@
-use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+use crate::text;
 
 /// Accepted internal representation produced by any parser.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ParsedData {
     /// A flat key-value config (section headers become `section.key`).
     Config(HashMap<String, String>),
@@ -14,6 +18,535 @@ pub enum ParsedData {
     },
 }
 
+impl ParsedData {
+    /// Number of entries: keys for `Config`, records for `JsonRecords`,
+    /// data rows for `CsvTable`.
+    pub fn len(&self) -> usize {
+        match self {
+            ParsedData::Config(map) => map.len(),
+            ParsedData::JsonRecords(records) => records.len(),
+            ParsedData::CsvTable { rows, .. } => rows.len(),
+        }
+    }
+
+    /// Returns `true` if the parse produced no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Entries of a `Config` sorted by fully-qualified key.
+    ///
+    /// Returns an empty list for other variants.
+    pub fn entries_sorted(&self) -> Vec<(&str, &str)> {
+        match self {
+            ParsedData::Config(map) => {
+                let mut entries: Vec<(&str, &str)> =
+                    map.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+                entries.sort_unstable();
+                entries
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Entries of a `Config` in storage order, which is unspecified; use
+    /// [`entries_sorted`](Self::entries_sorted) when the order matters.
+    ///
+    /// Yields nothing for other variants.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        let map = match self {
+            ParsedData::Config(map) => Some(map),
+            _ => None,
+        };
+        map.into_iter()
+            .flatten()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Every key path in the data: a `Config`'s fully-qualified keys,
+    /// sorted; a `CsvTable`'s headers, in column order; and the union of
+    /// all `JsonRecords` keys, sorted.
+    pub fn keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = match self {
+            ParsedData::Config(map) => map.keys().cloned().collect(),
+            ParsedData::JsonRecords(records) => {
+                records.iter().flat_map(|r| r.keys().cloned()).collect()
+            }
+            ParsedData::CsvTable { headers, .. } => return headers.clone(),
+        };
+        keys.sort_unstable();
+        keys.dedup();
+        keys
+    }
+
+    /// Value of the fully-qualified `key` (e.g. `network.port`) of a
+    /// `Config`.
+    ///
+    /// Returns `None` for unknown keys or other variants.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        match self {
+            ParsedData::Config(map) => map.get(key).map(String::as_str),
+            _ => None,
+        }
+    }
+
+    /// Value of `key` in a `Config` split on commas into a list, with
+    /// whitespace around each element trimmed, e.g. `alpha, beta` gives
+    /// `["alpha", "beta"]`.  The raw string is still available via
+    /// [`get`](Self::get).
+    ///
+    /// An empty value gives an empty list.  Returns `None` for unknown
+    /// keys or other variants.
+    pub fn get_list(&self, key: &str) -> Option<Vec<String>> {
+        let value = self.get(key)?;
+        if text::trim(value).is_empty() {
+            return Some(Vec::new());
+        }
+        Some(value.split(',').map(|item| text::trim(item).to_string()).collect())
+    }
+
+    /// Entries of section `name` of a `Config`, with the section name and
+    /// `separator` stripped from the keys, sorted by key.
+    ///
+    /// `separator` must be the one the config was parsed with.  Returns
+    /// an empty list for unknown sections or other variants.
+    pub fn section(&self, name: &str, separator: &str) -> Vec<(&str, &str)> {
+        let prefix = format!("{}{}", name, separator);
+        self.entries_sorted()
+            .into_iter()
+            .filter_map(|(k, v)| Some((k.strip_prefix(prefix.as_str())?, v)))
+            .collect()
+    }
+
+    /// Entries of a `Config` whose dotted key matches `pattern`, sorted
+    /// by key.  A `*` segment matches any one segment, so
+    /// `network.*` matches `network.port` but not `network.tls.cert`
+    /// or `network` itself.
+    ///
+    /// Returns an empty list if nothing matches or for other variants.
+    pub fn get_glob(&self, pattern: &str) -> Vec<(&str, &str)> {
+        let matches = |key: &str| {
+            let (mut keys, mut globs) = (key.split('.'), pattern.split('.'));
+            loop {
+                match (keys.next(), globs.next()) {
+                    (None, None) => return true,
+                    (Some(k), Some(g)) if g == "*" || g == k => {}
+                    _ => return false,
+                }
+            }
+        };
+        self.entries_sorted()
+            .into_iter()
+            .filter(|(k, _)| matches(k))
+            .collect()
+    }
+
+    /// Move every `from.*` key of a `Config` to `to.*`, keeping the
+    /// values, e.g. `db.host` becomes `database.host`.  Keys use the
+    /// default `.` section separator.
+    ///
+    /// Returns `Err` listing the clashing keys if any renamed key already
+    /// exists under `to`, leaving the config unchanged, or if this is not
+    /// a `Config`.  Renaming an absent section is a no-op.
+    pub fn rename_section(&mut self, from: &str, to: &str) -> Result<(), String> {
+        let map = match self {
+            ParsedData::Config(map) => map,
+            _ => return Err("rename_section is only supported on Config".into()),
+        };
+        if from == to {
+            return Ok(());
+        }
+
+        let prefix = format!("{}.", from);
+        let renames: Vec<(String, String)> = map
+            .keys()
+            .filter_map(|k| Some((k.clone(), format!("{}.{}", to, k.strip_prefix(&prefix)?))))
+            .collect();
+
+        let mut clashes: Vec<&str> = renames
+            .iter()
+            .filter(|(_, new)| map.contains_key(new))
+            .map(|(_, new)| new.as_str())
+            .collect();
+        if !clashes.is_empty() {
+            clashes.sort_unstable();
+            return Err(format!(
+                "Renaming section {:?} to {:?} would overwrite {:?}",
+                from, to, clashes
+            ));
+        }
+
+        for (old, new) in renames {
+            if let Some(value) = map.remove(&old) {
+                map.insert(new, value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Value of column `header` in data row `row` of a `CsvTable`.
+    ///
+    /// Returns `None` for out-of-range rows, unknown headers, or
+    /// non-table variants.
+    pub fn cell(&self, row: usize, header: &str) -> Option<&str> {
+        match self {
+            ParsedData::CsvTable { headers, rows } => {
+                let col = headers.iter().position(|h| h == header)?;
+                rows.get(row)?.get(col).map(String::as_str)
+            }
+            _ => None,
+        }
+    }
+
+    /// How often each value occurs in column `header` of a `CsvTable`.
+    /// Empty cells are counted under the empty string.
+    ///
+    /// Returns `Err` for an unknown header or a non-table variant.
+    pub fn value_counts(&self, header: &str) -> Result<HashMap<String, usize>, String> {
+        let (headers, rows) = match self {
+            ParsedData::CsvTable { headers, rows } => (headers, rows),
+            _ => return Err("value_counts is only supported on CsvTable".into()),
+        };
+        let col = headers
+            .iter()
+            .position(|h| h == header)
+            .ok_or_else(|| format!("Unknown column {:?}", header))?;
+
+        let mut counts = HashMap::new();
+        for row in rows {
+            let cell = row.get(col).map_or("", String::as_str);
+            *counts.entry(cell.to_string()).or_insert(0) += 1;
+        }
+        Ok(counts)
+    }
+
+    /// Number of distinct values in column `header` of a `CsvTable`,
+    /// with empty cells counting as one distinct empty value.
+    ///
+    /// Returns `Err` for an unknown header or a non-table variant.
+    pub fn distinct_count(&self, header: &str) -> Result<usize, String> {
+        self.value_counts(header).map(|counts| counts.len())
+    }
+
+    /// A new `CsvTable` with the same headers and only the rows whose
+    /// `header` cell satisfies `pred`, in their original order.
+    ///
+    /// Returns `Err` for an unknown header or a non-table variant.
+    pub fn filter_rows(
+        &self,
+        header: &str,
+        pred: impl Fn(&str) -> bool,
+    ) -> Result<ParsedData, String> {
+        let (headers, rows) = match self {
+            ParsedData::CsvTable { headers, rows } => (headers, rows),
+            _ => return Err("filter_rows is only supported on CsvTable".into()),
+        };
+        let col = headers
+            .iter()
+            .position(|h| h == header)
+            .ok_or_else(|| format!("Unknown column {:?}", header))?;
+
+        Ok(ParsedData::CsvTable {
+            headers: headers.clone(),
+            rows: rows
+                .iter()
+                .filter(|row| row.get(col).is_some_and(|cell| pred(cell)))
+                .cloned()
+                .collect(),
+        })
+    }
+
+    /// A new `CsvTable` with the same headers and data rows
+    /// `start..end`, e.g. for pagination.  A range running past the last
+    /// row is cut short, so it may come back with fewer rows or none.
+    ///
+    /// Returns `Err` if `start > end` or for a non-table variant.
+    pub fn rows_range(&self, start: usize, end: usize) -> Result<ParsedData, String> {
+        let (headers, rows) = match self {
+            ParsedData::CsvTable { headers, rows } => (headers, rows),
+            _ => return Err("rows_range is only supported on CsvTable".into()),
+        };
+        if start > end {
+            return Err(format!("Invalid row range {}..{}", start, end));
+        }
+        let end = end.min(rows.len());
+        Ok(ParsedData::CsvTable {
+            headers: headers.clone(),
+            rows: rows[start.min(end)..end].to_vec(),
+        })
+    }
+
+    /// The data as a uniform row set, whatever the source format.
+    ///
+    /// A `Config` becomes a single row, each JSON record becomes one row,
+    /// and each `CsvTable` data row becomes a map keyed by header.
+    pub fn as_rows(&self) -> Vec<HashMap<String, String>> {
+        match self {
+            ParsedData::Config(map) => vec![map.clone()],
+            ParsedData::JsonRecords(records) => records.clone(),
+            ParsedData::CsvTable { headers, rows } => rows
+                .iter()
+                .map(|row| headers.iter().cloned().zip(row.iter().cloned()).collect())
+                .collect(),
+        }
+    }
+
+    /// Transpose a `CsvTable`, turning columns into rows.
+    ///
+    /// The result's first column, `column`, holds the original headers;
+    /// original data row `i` becomes column `row_i`.  Every cell is
+    /// copied, so this is meant for small tables such as reports.
+    pub fn transpose(&self) -> Result<ParsedData, String> {
+        let (headers, rows) = match self {
+            ParsedData::CsvTable { headers, rows } => (headers, rows),
+            _ => return Err("transpose is only supported on CsvTable".into()),
+        };
+
+        let new_headers = std::iter::once("column".to_string())
+            .chain((0..rows.len()).map(|i| format!("row_{}", i)))
+            .collect();
+        let new_rows = headers
+            .iter()
+            .enumerate()
+            .map(|(col, header)| {
+                std::iter::once(header.clone())
+                    .chain(rows.iter().map(|row| row.get(col).cloned().unwrap_or_default()))
+                    .collect()
+            })
+            .collect();
+
+        Ok(ParsedData::CsvTable {
+            headers: new_headers,
+            rows: new_rows,
+        })
+    }
+
+    /// Pivot a long-format `CsvTable` into a wide one.
+    ///
+    /// The result has one row per distinct `index` value and one column
+    /// per distinct `columns` value, both in order of first appearance,
+    /// after a leading `index` column.  Each cell holds the `values`
+    /// cell of the matching input row, or is empty if there was none.
+    ///
+    /// Returns `Err` for a non-table variant, an unknown header, or two
+    /// rows with the same `index` and `columns` values.
+    pub fn pivot(&self, index: &str, columns: &str, values: &str) -> Result<ParsedData, String> {
+        let (headers, rows) = match self {
+            ParsedData::CsvTable { headers, rows } => (headers, rows),
+            _ => return Err("pivot is only supported on CsvTable".into()),
+        };
+        let col = |header: &str| {
+            headers
+                .iter()
+                .position(|h| h == header)
+                .ok_or_else(|| format!("Unknown column {:?}", header))
+        };
+        let (index_col, columns_col, values_col) = (col(index)?, col(columns)?, col(values)?);
+        let cell = |row: &Vec<String>, c: usize| row.get(c).cloned().unwrap_or_default();
+
+        let mut new_headers = vec![index.to_string()];
+        let mut column_of: HashMap<String, usize> = HashMap::new();
+        let mut row_of: HashMap<String, usize> = HashMap::new();
+        let mut new_rows: Vec<Vec<String>> = Vec::new();
+        let mut filled = HashSet::new();
+        for row in rows {
+            let (key, category) = (cell(row, index_col), cell(row, columns_col));
+            let c = *column_of.entry(category.clone()).or_insert_with(|| {
+                new_headers.push(category.clone());
+                new_headers.len() - 1
+            });
+            let r = *row_of.entry(key.clone()).or_insert_with(|| {
+                new_rows.push(vec![key.clone()]);
+                new_rows.len() - 1
+            });
+
+            if !filled.insert((r, c)) {
+                return Err(format!(
+                    "Duplicate entry for {} {:?} and {} {:?}",
+                    index, key, columns, category
+                ));
+            }
+            let target = &mut new_rows[r];
+            target.resize(target.len().max(c + 1), String::new());
+            target[c] = cell(row, values_col);
+        }
+        for row in &mut new_rows {
+            row.resize(new_headers.len(), String::new());
+        }
+
+        Ok(ParsedData::CsvTable {
+            headers: new_headers,
+            rows: new_rows,
+        })
+    }
+
+    /// Append the rows of `other` to this `CsvTable`.
+    ///
+    /// Both values must be `CsvTable`s with exactly the same headers (in
+    /// the same order); otherwise `Err` is returned and `self` is left
+    /// unchanged.
+    pub fn extend(&mut self, other: ParsedData) -> Result<(), String> {
+        let (headers, rows) = match self {
+            ParsedData::CsvTable { headers, rows } => (headers, rows),
+            _ => return Err("extend is only supported on CsvTable".into()),
+        };
+
+        match other {
+            ParsedData::CsvTable {
+                headers: other_headers,
+                rows: other_rows,
+            } => {
+                if *headers != other_headers {
+                    return Err(format!(
+                        "Header mismatch: expected {:?}, got {:?}",
+                        headers, other_headers
+                    ));
+                }
+                rows.extend(other_rows);
+                Ok(())
+            }
+            _ => Err("extend expects a CsvTable argument".into()),
+        }
+    }
+
+    /// Remove `CsvTable` rows that are identical across all columns,
+    /// keeping the first occurrence of each in its original position.
+    ///
+    /// Other variants are left unchanged.
+    pub fn dedup_rows(&mut self) {
+        if let ParsedData::CsvTable { rows, .. } = self {
+            let mut seen = HashSet::new();
+            rows.retain(|row| seen.insert(row.clone()));
+        }
+    }
+
+    /// Replace every data cell of a `CsvTable` with `f` applied to it,
+    /// e.g. to trim, case-fold or redact values.  Headers are not
+    /// touched.
+    ///
+    /// Other variants are left unchanged.
+    pub fn map_cells(&mut self, f: impl Fn(&str) -> String) {
+        if let ParsedData::CsvTable { rows, .. } = self {
+            for cell in rows.iter_mut().flatten() {
+                *cell = f(cell);
+            }
+        }
+    }
+
+    /// Like [`dedup_rows`](Self::dedup_rows), but two rows count as
+    /// duplicates when they agree on the given `headers` only.
+    ///
+    /// Returns `Err` for an unknown header or a non-table variant.
+    pub fn dedup_by(&mut self, headers: &[&str]) -> Result<(), String> {
+        let (all_headers, rows) = match self {
+            ParsedData::CsvTable { headers, rows } => (headers, rows),
+            _ => return Err("dedup_by is only supported on CsvTable".into()),
+        };
+        let cols = headers
+            .iter()
+            .map(|h| {
+                all_headers
+                    .iter()
+                    .position(|a| a == h)
+                    .ok_or_else(|| format!("Unknown column {:?}", h))
+            })
+            .collect::<Result<Vec<usize>, String>>()?;
+
+        let mut seen = HashSet::new();
+        rows.retain(|row| {
+            let key: Vec<Option<String>> = cols.iter().map(|&c| row.get(c).cloned()).collect();
+            seen.insert(key)
+        });
+        Ok(())
+    }
+
+    /// Hash of the content, independent of `HashMap` iteration order:
+    /// `Config` entries and each JSON record's fields are hashed in
+    /// sorted-key order, a `CsvTable` as its headers then its rows.
+    ///
+    /// Equal data always hashes equal within one build; the value is not
+    /// meant to be persisted across Rust versions.
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        match self {
+            ParsedData::Config(map) => {
+                0u8.hash(&mut hasher);
+                hash_sorted(map, &mut hasher);
+            }
+            ParsedData::JsonRecords(records) => {
+                1u8.hash(&mut hasher);
+                records.len().hash(&mut hasher);
+                for record in records {
+                    hash_sorted(record, &mut hasher);
+                }
+            }
+            ParsedData::CsvTable { headers, rows } => {
+                2u8.hash(&mut hasher);
+                headers.hash(&mut hasher);
+                rows.hash(&mut hasher);
+            }
+        }
+        hasher.finish()
+    }
+}
+
+/// Hash the entries of `map` in sorted-key order.
+fn hash_sorted(map: &HashMap<String, String>, hasher: &mut impl Hasher) {
+    let mut entries: Vec<(&String, &String)> = map.iter().collect();
+    entries.sort_unstable();
+    entries.hash(hasher);
+}
+
+/// Line-level statistics about a parse, for data-quality reporting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Summary {
+    /// Number of parsed entries (see [`ParsedData::len`]).
+    pub entries: usize,
+    /// Lines that were empty or held only [`text::is_blank`] characters.
+    pub blank_lines: usize,
+    /// Lines skipped as comments by the format's parser.
+    pub comment_lines: usize,
+    /// All other lines, including headers and section lines.
+    pub data_lines: usize,
+}
+
+impl Summary {
+    /// Classify every line of `content`, treating lines for which
+    /// `is_comment` returns `true` (after trimming) as comments.
+    pub fn count_lines(content: &str, is_comment: impl Fn(&str) -> bool) -> Summary {
+        let mut summary = Summary::default();
+        for line in content.lines().map(text::trim) {
+            if line.is_empty() {
+                summary.blank_lines += 1;
+            } else if is_comment(line) {
+                summary.comment_lines += 1;
+            } else {
+                summary.data_lines += 1;
+            }
+        }
+        summary
+    }
+}
+
+/// Byte range of a parsed entity within the original input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    /// Byte offset of the first byte.
+    pub offset: usize,
+    /// Length in bytes.
+    pub len: usize,
+}
+
+impl Span {
+    /// Span of `slice`, which must be a sub-slice of `content`.
+    pub fn of(content: &str, slice: &str) -> Span {
+        Span {
+            offset: slice.as_ptr() as usize - content.as_ptr() as usize,
+            len: slice.len(),
+        }
+    }
+}
+
 /// Print a summary of the parsed result.
 pub fn summarise(data: &ParsedData) {
     match data {
@@ -32,3 +565,334 @@ pub fn summarise(data: &ParsedData) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_len_and_is_empty() {
+        let mut map = HashMap::new();
+        map.insert("a".to_string(), "1".to_string());
+        map.insert("b".to_string(), "2".to_string());
+        let data = ParsedData::Config(map);
+        assert_eq!(data.len(), 2);
+        assert!(!data.is_empty());
+
+        let empty = ParsedData::Config(HashMap::new());
+        assert_eq!(empty.len(), 0);
+        assert!(empty.is_empty());
+    }
+
+    fn config(pairs: &[(&str, &str)]) -> ParsedData {
+        ParsedData::Config(
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn iter_supports_iterator_combinators() {
+        let data = config(&[("db.host", "localhost"), ("db.port", "5432"), ("name", "app")]);
+        let mut db: Vec<(&str, &str)> = data
+            .iter()
+            .filter_map(|(k, v)| Some((k.strip_prefix("db.")?, v)))
+            .collect();
+        db.sort_unstable();
+        assert_eq!(db, vec![("host", "localhost"), ("port", "5432")]);
+        assert_eq!(data.iter().count(), 3);
+        assert_eq!(table(&["a"], &[&["1"]]).iter().count(), 0);
+    }
+
+    #[test]
+    fn content_hash_ignores_insertion_order() {
+        let forward = config(&[("a", "1"), ("b", "2"), ("c", "3")]);
+        let mut backward = HashMap::with_capacity(64);
+        for (k, v) in [("c", "3"), ("b", "2"), ("a", "1")] {
+            backward.insert(k.to_string(), v.to_string());
+        }
+        let backward = ParsedData::Config(backward);
+        assert_eq!(forward.content_hash(), backward.content_hash());
+
+        assert_ne!(forward.content_hash(), config(&[("a", "1"), ("b", "2")]).content_hash());
+        let grid = table(&["a", "b"], &[&["1", "2"]]);
+        assert_ne!(grid.content_hash(), table(&["b", "a"], &[&["1", "2"]]).content_hash());
+    }
+
+    #[test]
+    fn keys_lists_config_keys_and_table_headers() {
+        let data = config(&[("network.port", "8080"), ("name", "app")]);
+        assert_eq!(data.keys(), vec!["name", "network.port"]);
+
+        let grid = table(&["id", "name", "email"], &[&["1", "a", "a@x"]]);
+        assert_eq!(grid.keys(), vec!["id", "name", "email"]);
+
+        let mut first = HashMap::new();
+        first.insert("id".to_string(), "1".to_string());
+        let mut second = first.clone();
+        second.insert("tag".to_string(), "x".to_string());
+        assert_eq!(ParsedData::JsonRecords(vec![second, first]).keys(), vec!["id", "tag"]);
+    }
+
+    #[test]
+    fn value_counts_and_distinct_count() {
+        let data = table(
+            &["id", "color"],
+            &[&["1", "red"], &["2", ""], &["3", "red"], &["4", "blue"], &["5", ""]],
+        );
+        let counts = data.value_counts("color").unwrap();
+        assert_eq!(counts.len(), 3);
+        assert_eq!(counts["red"], 2);
+        assert_eq!(counts["blue"], 1);
+        assert_eq!(counts[""], 2);
+        assert_eq!(data.distinct_count("color"), Ok(3));
+        assert_eq!(data.distinct_count("id"), Ok(5));
+        assert!(data.distinct_count("size").is_err());
+    }
+
+    #[test]
+    fn rename_section_moves_its_keys() {
+        let mut data = config(&[("db.host", "localhost"), ("db.port", "5432"), ("dbx", "1")]);
+        data.rename_section("db", "database").unwrap();
+        assert_eq!(
+            data.entries_sorted(),
+            vec![
+                ("database.host", "localhost"),
+                ("database.port", "5432"),
+                ("dbx", "1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn rename_section_rejects_collisions() {
+        let mut data = config(&[("db.host", "a"), ("db.port", "1"), ("database.port", "2")]);
+        let before = data.clone();
+        let err = data.rename_section("db", "database").unwrap_err();
+        assert!(err.contains("database.port"), "{}", err);
+        assert_eq!(data, before);
+    }
+
+    #[test]
+    fn csv_table_len_and_is_empty() {
+        let data = ParsedData::CsvTable {
+            headers: vec!["a".into(), "b".into()],
+            rows: vec![vec!["1".into(), "2".into()], vec!["3".into(), "4".into()]],
+        };
+        assert_eq!(data.len(), 2);
+        assert!(!data.is_empty());
+
+        let header_only = ParsedData::CsvTable {
+            headers: vec!["a".into(), "b".into()],
+            rows: Vec::new(),
+        };
+        assert_eq!(header_only.len(), 0);
+        assert!(header_only.is_empty());
+    }
+
+    fn table(headers: &[&str], rows: &[&[&str]]) -> ParsedData {
+        ParsedData::CsvTable {
+            headers: headers.iter().map(|h| h.to_string()).collect(),
+            rows: rows
+                .iter()
+                .map(|r| r.iter().map(|f| f.to_string()).collect())
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn entries_sorted_orders_by_full_key() {
+        let mut map = HashMap::new();
+        for (k, v) in [
+            ("network.timeout", "30"),
+            ("metadata.version", "1.0"),
+            ("network.port", "8080"),
+            ("metadata.name", "test_app"),
+        ] {
+            map.insert(k.to_string(), v.to_string());
+        }
+        assert_eq!(
+            ParsedData::Config(map).entries_sorted(),
+            vec![
+                ("metadata.name", "test_app"),
+                ("metadata.version", "1.0"),
+                ("network.port", "8080"),
+                ("network.timeout", "30"),
+            ]
+        );
+    }
+
+    #[test]
+    fn cell_lookup_by_row_and_header() {
+        let data = table(&["id", "city"], &[&["1", "NYC"], &["2", "LA"]]);
+        assert_eq!(data.cell(1, "city"), Some("LA"));
+        assert_eq!(data.cell(2, "city"), None);
+        assert_eq!(data.cell(0, "country"), None);
+        assert_eq!(ParsedData::Config(HashMap::new()).cell(0, "id"), None);
+    }
+
+    #[test]
+    fn transpose_two_by_two() {
+        let data = table(&["name", "age"], &[&["alice", "30"], &["bob", "25"]]);
+        match data.transpose().unwrap() {
+            ParsedData::CsvTable { headers, rows } => {
+                assert_eq!(headers, vec!["column", "row_0", "row_1"]);
+                assert_eq!(
+                    rows,
+                    vec![vec!["name", "alice", "bob"], vec!["age", "30", "25"]]
+                );
+            }
+            other => panic!("expected CsvTable, got {:?}", other),
+        }
+        assert!(ParsedData::Config(HashMap::new()).transpose().is_err());
+    }
+
+    #[test]
+    fn extend_appends_rows_with_matching_headers() {
+        let mut day1 = table(&["id", "total"], &[&["1", "10"]]);
+        let day2 = table(&["id", "total"], &[&["2", "20"], &["3", "30"]]);
+        day1.extend(day2).unwrap();
+        assert_eq!(day1.len(), 3);
+        match day1 {
+            ParsedData::CsvTable { rows, .. } => assert_eq!(rows[2], vec!["3", "30"]),
+            other => panic!("expected CsvTable, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn extend_rejects_header_mismatch() {
+        let mut day1 = table(&["id", "total"], &[&["1", "10"]]);
+        let other = table(&["id", "amount"], &[&["2", "20"]]);
+        assert!(day1.extend(other).is_err());
+        assert_eq!(day1.len(), 1);
+
+        assert!(day1.extend(ParsedData::Config(HashMap::new())).is_err());
+    }
+
+    #[test]
+    fn as_rows_for_each_variant() {
+        let mut map = HashMap::new();
+        map.insert("port".to_string(), "8080".to_string());
+        assert_eq!(ParsedData::Config(map.clone()).as_rows(), vec![map.clone()]);
+
+        let records = ParsedData::JsonRecords(vec![map.clone(), HashMap::new()]);
+        assert_eq!(records.as_rows(), vec![map, HashMap::new()]);
+
+        let rows = table(&["id", "city"], &[&["1", "NYC"], &["2", "LA"]]).as_rows();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1]["id"], "2");
+        assert_eq!(rows[1]["city"], "LA");
+    }
+
+    #[test]
+    fn filter_rows_keeps_matching_rows() {
+        let data = table(
+            &["id", "status"],
+            &[&["1", "active"], &["2", "closed"], &["3", "active"]],
+        );
+        let active = data.filter_rows("status", |s| s == "active").unwrap();
+        assert_eq!(active.len(), 2);
+        assert_eq!(active.cell(1, "id"), Some("3"));
+        assert!(data.filter_rows("state", |_| true).is_err());
+    }
+
+    #[test]
+    fn dedup_rows_keeps_first_occurrence() {
+        let mut data = table(
+            &["id", "city"],
+            &[&["1", "NYC"], &["2", "LA"], &["1", "NYC"], &["3", "NYC"]],
+        );
+        data.dedup_rows();
+        assert_eq!(data, table(&["id", "city"], &[&["1", "NYC"], &["2", "LA"], &["3", "NYC"]]));
+    }
+
+    #[test]
+    fn dedup_by_a_key_column() {
+        let mut data = table(
+            &["id", "city"],
+            &[&["1", "NYC"], &["2", "LA"], &["1", "Boston"]],
+        );
+        data.dedup_by(&["id"]).unwrap();
+        assert_eq!(data, table(&["id", "city"], &[&["1", "NYC"], &["2", "LA"]]));
+        assert!(data.dedup_by(&["zip"]).is_err());
+    }
+
+    #[test]
+    fn pivot_spreads_categories_into_columns() {
+        let long = table(
+            &["region", "quarter", "sales"],
+            &[
+                &["north", "q1", "10"],
+                &["north", "q2", "12"],
+                &["south", "q2", "7"],
+                &["east", "q1", "3"],
+            ],
+        );
+        let wide = long.pivot("region", "quarter", "sales").unwrap();
+        assert_eq!(
+            wide,
+            table(
+                &["region", "q1", "q2"],
+                &[&["north", "10", "12"], &["south", "", "7"], &["east", "3", ""]]
+            )
+        );
+
+        let mut dup = long.clone();
+        dup.extend(table(&["region", "quarter", "sales"], &[&["south", "q2", "8"]]))
+            .unwrap();
+        let err = dup.pivot("region", "quarter", "sales").unwrap_err();
+        assert!(err.starts_with("Duplicate entry"), "{}", err);
+        assert!(long.pivot("region", "month", "sales").is_err());
+    }
+
+    #[test]
+    fn map_cells_transforms_data_but_not_headers() {
+        let mut data = table(&["name", "city"], &[&["alice", "nyc"], &["bob", ""]]);
+        data.map_cells(str::to_uppercase);
+        assert_eq!(
+            data,
+            table(&["name", "city"], &[&["ALICE", "NYC"], &["BOB", ""]])
+        );
+
+        let mut config = config(&[("name", "app")]);
+        config.map_cells(|_| "redacted".into());
+        assert_eq!(config.get("name"), Some("app"));
+    }
+
+    #[test]
+    fn glob_matches_one_segment_per_star() {
+        let data = config(&[
+            ("name", "app"),
+            ("network.port", "8080"),
+            ("network.timeout", "30"),
+            ("network.tls.cert", "a.pem"),
+            ("db.port", "5432"),
+        ]);
+        assert_eq!(
+            data.get_glob("network.*"),
+            vec![("network.port", "8080"), ("network.timeout", "30")]
+        );
+        assert_eq!(
+            data.get_glob("*.port"),
+            vec![("db.port", "5432"), ("network.port", "8080")]
+        );
+        assert!(data.get_glob("cache.*").is_empty());
+    }
+
+    #[test]
+    fn rows_range_slices_and_clamps() {
+        let data = table(&["n"], &[&["0"], &["1"], &["2"], &["3"]]);
+        assert_eq!(data.rows_range(1, 3).unwrap(), table(&["n"], &[&["1"], &["2"]]));
+        assert_eq!(data.rows_range(2, 10).unwrap(), table(&["n"], &[&["2"], &["3"]]));
+        assert_eq!(data.rows_range(6, 10).unwrap(), table(&["n"], &[]));
+    }
+
+    #[test]
+    fn rows_range_rejects_reversed_bounds() {
+        let data = table(&["n"], &[&["0"], &["1"]]);
+        let err = data.rows_range(2, 1).unwrap_err();
+        assert_eq!(err, "Invalid row range 2..1");
+    }
+}
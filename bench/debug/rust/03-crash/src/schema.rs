@@ -0,0 +1,487 @@
+use std::time::Duration;
+
+use crate::processor::ParsedData;
+use crate::text;
+
+/// The type a config value is expected to have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueKind {
+    Int,
+    Bool,
+    Str,
+    Float,
+}
+
+impl ValueKind {
+    /// Returns `true` if `value` can be read as this kind.
+    ///
+    /// Integers are accepted as floats.  Booleans accept `true`/`false`,
+    /// `yes`/`no` and `on`/`off`, case-insensitively.  Any value is a
+    /// valid `Str`.
+    pub fn matches(self, value: &str) -> bool {
+        match self {
+            ValueKind::Int => value.parse::<i64>().is_ok(),
+            ValueKind::Float => value.parse::<f64>().is_ok(),
+            ValueKind::Bool => parse_bool(value).is_some(),
+            ValueKind::Str => true,
+        }
+    }
+
+    /// `value` rewritten in this kind's canonical form: integers without
+    /// a `+` sign or leading zeros, floats with a decimal point, booleans
+    /// as `true`/`false`.  Returns `None` if `value` does not match.
+    pub fn canonicalize(self, value: &str) -> Option<String> {
+        match self {
+            ValueKind::Int => value.parse::<i64>().ok().map(|n| n.to_string()),
+            ValueKind::Float => value.parse::<f64>().ok().map(|n| format!("{:?}", n)),
+            ValueKind::Bool => parse_bool(value).map(|b| b.to_string()),
+            ValueKind::Str => Some(value.to_string()),
+        }
+    }
+}
+
+/// Parse a config-style boolean.
+pub fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" => Some(true),
+        "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// Parse a whole number followed by an optional unit suffix (matched
+/// case-insensitively, after optional whitespace), multiplying by the
+/// unit's factor.  A bare number uses the first unit.
+fn parse_with_unit(value: &str, units: &[(&str, u64)]) -> Option<u64> {
+    let value = text::trim(value);
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (number, suffix) = value.split_at(split);
+    let number: u64 = number.parse().ok()?;
+    let suffix = text::trim(suffix);
+    let factor = if suffix.is_empty() {
+        units[0].1
+    } else {
+        units.iter().find(|(unit, _)| unit.eq_ignore_ascii_case(suffix))?.1
+    };
+    number.checked_mul(factor)
+}
+
+/// Parse a config-style duration: a whole number of seconds, optionally
+/// suffixed with `s`, `m` or `h`, e.g. `30`, `30s` or `5m`.
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    parse_with_unit(value, &[("s", 1), ("m", 60), ("h", 3600)]).map(Duration::from_secs)
+}
+
+/// Parse a config-style byte size: a whole number of bytes, optionally
+/// suffixed with `B`, `KB`, `MB` or `GB` (powers of 1024), e.g. `64MB`.
+pub fn parse_byte_size(value: &str) -> Option<u64> {
+    const KB: u64 = 1024;
+    parse_with_unit(
+        value,
+        &[("B", 1), ("KB", KB), ("MB", KB * KB), ("GB", KB * KB * KB)],
+    )
+}
+
+/// A type a config value can be read as with [`ParsedData::get_or`].
+pub trait FromConfigValue: Sized {
+    /// Read `value`, or `None` if it is not valid for this type.
+    fn from_config_value(value: &str) -> Option<Self>;
+}
+
+impl FromConfigValue for i64 {
+    fn from_config_value(value: &str) -> Option<Self> {
+        value.parse().ok()
+    }
+}
+
+impl FromConfigValue for f64 {
+    fn from_config_value(value: &str) -> Option<Self> {
+        value.parse().ok()
+    }
+}
+
+impl FromConfigValue for bool {
+    fn from_config_value(value: &str) -> Option<Self> {
+        parse_bool(value)
+    }
+}
+
+impl FromConfigValue for String {
+    fn from_config_value(value: &str) -> Option<Self> {
+        Some(value.to_string())
+    }
+}
+
+/// A single schema violation found by [`ParsedData::validate_schema`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaError {
+    /// The data being validated is not a `Config`.
+    NotAConfig,
+    /// A required key is absent.
+    Missing { key: String },
+    /// A key is present but its value is not of the expected kind.
+    WrongType {
+        key: String,
+        expected: ValueKind,
+        value: String,
+    },
+}
+
+/// A single violation found by [`ParsedData::validate_table_schema`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaViolation {
+    /// The data being validated is not a `CsvTable`.
+    NotATable,
+    /// A declared column is absent from the headers.
+    MissingColumn { column: String },
+    /// A cell does not match its column's declared kind.  `row` is the
+    /// 0-based data row index.
+    BadCell {
+        row: usize,
+        column: String,
+        expected: ValueKind,
+        value: String,
+    },
+}
+
+impl ParsedData {
+    /// Rewrite every cell of column `header` of a `CsvTable` in `kind`'s
+    /// canonical form (see [`ValueKind::canonicalize`]).
+    ///
+    /// If any cell does not match `kind`, the table is left unchanged and
+    /// the failing `(row index, value)` pairs are returned.  If the data
+    /// is not a `CsvTable` or has no column `header`, the error is a
+    /// single pair whose row index is `usize::MAX` and whose value is a
+    /// message saying why.
+    pub fn coerce_column(
+        &mut self,
+        header: &str,
+        kind: ValueKind,
+    ) -> Result<(), Vec<(usize, String)>> {
+        let structural = |message: String| vec![(usize::MAX, message)];
+        let (headers, rows) = match self {
+            ParsedData::CsvTable { headers, rows } => (headers, rows),
+            _ => return Err(structural("coerce_column expects a CsvTable".into())),
+        };
+        let col = headers
+            .iter()
+            .position(|h| h == header)
+            .ok_or_else(|| structural(format!("Unknown column {:?}", header)))?;
+
+        let mut coerced = Vec::with_capacity(rows.len());
+        let mut failures = Vec::new();
+        for (i, row) in rows.iter().enumerate() {
+            let cell = row.get(col).map_or("", String::as_str);
+            match kind.canonicalize(cell) {
+                Some(value) => coerced.push(value),
+                None => failures.push((i, cell.to_string())),
+            }
+        }
+        if !failures.is_empty() {
+            return Err(failures);
+        }
+
+        for (row, value) in rows.iter_mut().zip(coerced) {
+            if let Some(cell) = row.get_mut(col) {
+                *cell = value;
+            }
+        }
+        Ok(())
+    }
+
+    /// Value of `key` in this `Config` read as a `T`, or `default` if the
+    /// key is missing or its value does not parse (see
+    /// [`ValueKind::matches`] for the accepted forms).
+    pub fn get_or<T: FromConfigValue>(&self, key: &str, default: T) -> T {
+        self.get(key).and_then(T::from_config_value).unwrap_or(default)
+    }
+
+    /// Value of `key` in this `Config` read as a duration (see
+    /// [`parse_duration`]).  Returns `None` if the key is missing or the
+    /// value or its suffix is invalid.
+    pub fn get_duration(&self, key: &str) -> Option<Duration> {
+        self.get(key).and_then(parse_duration)
+    }
+
+    /// Value of `key` in this `Config` read as a byte size (see
+    /// [`parse_byte_size`]).  Returns `None` if the key is missing or the
+    /// value or its suffix is invalid.
+    pub fn get_bytes(&self, key: &str) -> Option<u64> {
+        self.get(key).and_then(parse_byte_size)
+    }
+
+    /// Value of `key` in this `Config` read with `parse`, for values that
+    /// must be one of a fixed set, e.g. `level = info`.
+    ///
+    /// Returns `Err` naming the key if it is missing, or naming the key
+    /// and raw value if `parse` rejects it.
+    pub fn get_enum<T>(&self, key: &str, parse: impl Fn(&str) -> Option<T>) -> Result<T, String> {
+        let value = self.get(key).ok_or_else(|| format!("Missing key {:?}", key))?;
+        parse(value).ok_or_else(|| format!("Invalid value {:?} for key {:?}", value, key))
+    }
+
+    /// Check that every `(key, kind)` in `required` is present in this
+    /// `Config` with a value of the given kind.
+    ///
+    /// All violations are collected rather than stopping at the first.
+    pub fn validate_schema(&self, required: &[(&str, ValueKind)]) -> Result<(), Vec<SchemaError>> {
+        let map = match self {
+            ParsedData::Config(map) => map,
+            _ => return Err(vec![SchemaError::NotAConfig]),
+        };
+
+        let errors: Vec<SchemaError> = required
+            .iter()
+            .filter_map(|&(key, kind)| match map.get(key) {
+                None => Some(SchemaError::Missing {
+                    key: key.to_string(),
+                }),
+                Some(value) if !kind.matches(value) => Some(SchemaError::WrongType {
+                    key: key.to_string(),
+                    expected: kind,
+                    value: value.clone(),
+                }),
+                Some(_) => None,
+            })
+            .collect();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Check this `CsvTable` against a declared `schema` of column kinds.
+    ///
+    /// The schema is authoritative: every named column must exist and
+    /// every one of its cells must match the kind (a missing cell counts
+    /// as empty).  Columns not named in `schema` are ignored.  All
+    /// violations are collected, column by column in schema order.
+    pub fn validate_table_schema(
+        &self,
+        schema: &[(&str, ValueKind)],
+    ) -> Result<(), Vec<SchemaViolation>> {
+        let (headers, rows) = match self {
+            ParsedData::CsvTable { headers, rows } => (headers, rows),
+            _ => return Err(vec![SchemaViolation::NotATable]),
+        };
+
+        let mut violations = Vec::new();
+        for &(column, kind) in schema {
+            let Some(col) = headers.iter().position(|h| h == column) else {
+                violations.push(SchemaViolation::MissingColumn {
+                    column: column.to_string(),
+                });
+                continue;
+            };
+            for (row, cells) in rows.iter().enumerate() {
+                let value = cells.get(col).map_or("", String::as_str);
+                if !kind.matches(value) {
+                    violations.push(SchemaViolation::BadCell {
+                        row,
+                        column: column.to_string(),
+                        expected: kind,
+                        value: value.to_string(),
+                    });
+                }
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn config(pairs: &[(&str, &str)]) -> ParsedData {
+        ParsedData::Config(
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<HashMap<_, _>>(),
+        )
+    }
+
+    #[test]
+    fn valid_config_passes() {
+        let data = config(&[("network.port", "8080"), ("debug", "on"), ("ratio", "0.5")]);
+        let schema = [
+            ("network.port", ValueKind::Int),
+            ("debug", ValueKind::Bool),
+            ("ratio", ValueKind::Float),
+        ];
+        assert_eq!(data.validate_schema(&schema), Ok(()));
+    }
+
+    #[test]
+    fn collects_every_violation() {
+        let data = config(&[("network.port", "eighty"), ("metadata.name", "app")]);
+        let schema = [
+            ("network.port", ValueKind::Int),
+            ("network.timeout", ValueKind::Int),
+            ("metadata.name", ValueKind::Str),
+        ];
+        let errors = data.validate_schema(&schema).unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                SchemaError::WrongType {
+                    key: "network.port".into(),
+                    expected: ValueKind::Int,
+                    value: "eighty".into(),
+                },
+                SchemaError::Missing {
+                    key: "network.timeout".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn get_or_falls_back_to_the_default() {
+        let config = config(&[
+            ("port", "8080"),
+            ("debug", "yes"),
+            ("ratio", "0.5"),
+            ("name", "app"),
+        ]);
+
+        assert_eq!(config.get_or("port", 80i64), 8080);
+        assert!(config.get_or("debug", false));
+        assert_eq!(config.get_or("ratio", 1.0), 0.5);
+        assert_eq!(config.get_or("name", String::from("default")), "app");
+
+        assert_eq!(config.get_or("timeout", 30i64), 30);
+        assert!(!config.get_or("verbose", false));
+
+        assert_eq!(config.get_or("name", 7i64), 7);
+        assert!(config.get_or("port", true));
+    }
+
+    #[test]
+    fn get_enum_parses_or_reports_the_raw_value() {
+        #[derive(Debug, PartialEq)]
+        enum Level {
+            Info,
+            Warn,
+        }
+        let parse = |v: &str| match v {
+            "info" => Some(Level::Info),
+            "warn" => Some(Level::Warn),
+            _ => None,
+        };
+
+        let data = config(&[("level", "info"), ("fallback", "verbose")]);
+        assert_eq!(data.get_enum("level", parse), Ok(Level::Info));
+        assert_eq!(
+            data.get_enum("fallback", parse),
+            Err("Invalid value \"verbose\" for key \"fallback\"".to_string())
+        );
+        assert!(data.get_enum("missing", parse).is_err());
+    }
+
+    #[test]
+    fn durations_and_byte_sizes_accept_suffixes() {
+        let data = config(&[
+            ("timeout", "30s"),
+            ("retry", "5 m"),
+            ("bare", "30"),
+            ("cache", "64MB"),
+            ("bad", "30x"),
+        ]);
+        assert_eq!(data.get_duration("timeout"), Some(Duration::from_secs(30)));
+        assert_eq!(data.get_duration("retry"), Some(Duration::from_secs(300)));
+        assert_eq!(data.get_duration("bare"), Some(Duration::from_secs(30)));
+        assert_eq!(data.get_duration("bad"), None);
+        assert_eq!(data.get_duration("missing"), None);
+
+        assert_eq!(data.get_bytes("cache"), Some(64 * 1024 * 1024));
+        assert_eq!(data.get_bytes("bare"), Some(30));
+        assert_eq!(data.get_bytes("timeout"), None);
+        assert_eq!(parse_byte_size("99999999999GB"), None);
+    }
+
+    fn column(values: &[&str]) -> ParsedData {
+        ParsedData::CsvTable {
+            headers: vec!["n".into()],
+            rows: values.iter().map(|v| vec![v.to_string()]).collect(),
+        }
+    }
+
+    #[test]
+    fn coerce_clean_numeric_column() {
+        let mut data = column(&["007", "-3", "+12"]);
+        data.coerce_column("n", ValueKind::Int).unwrap();
+        assert_eq!(data, column(&["7", "-3", "12"]));
+
+        data.coerce_column("n", ValueKind::Float).unwrap();
+        assert_eq!(data, column(&["7.0", "-3.0", "12.0"]));
+    }
+
+    #[test]
+    fn coerce_reports_failing_rows_and_leaves_table_unchanged() {
+        let mut data = column(&["1", "two", "3", "4.5"]);
+        let failures = data.coerce_column("n", ValueKind::Int).unwrap_err();
+        assert_eq!(failures, vec![(1, "two".to_string()), (3, "4.5".to_string())]);
+        assert_eq!(data, column(&["1", "two", "3", "4.5"]));
+    }
+
+    #[test]
+    fn coerce_distinguishes_structural_errors() {
+        let mut data = column(&["1"]);
+        assert_eq!(
+            data.coerce_column("m", ValueKind::Int),
+            Err(vec![(usize::MAX, "Unknown column \"m\"".to_string())])
+        );
+        let mut config = ParsedData::Config(HashMap::new());
+        assert_eq!(
+            config.coerce_column("n", ValueKind::Int),
+            Err(vec![(usize::MAX, "coerce_column expects a CsvTable".to_string())])
+        );
+    }
+
+    #[test]
+    fn table_schema_reports_bad_cells_and_missing_columns() {
+        let data = ParsedData::CsvTable {
+            headers: vec!["id".into(), "name".into()],
+            rows: vec![
+                vec!["1".into(), "alpha".into()],
+                vec!["two".into(), "beta".into()],
+                vec!["3".into(), "gamma".into()],
+                vec!["4.5".into(), "delta".into()],
+            ],
+        };
+        assert_eq!(
+            data.validate_table_schema(&[("id", ValueKind::Str), ("name", ValueKind::Str)]),
+            Ok(())
+        );
+
+        let violations = data
+            .validate_table_schema(&[("id", ValueKind::Int), ("score", ValueKind::Float)])
+            .unwrap_err();
+        let bad = |row: usize, value: &str| SchemaViolation::BadCell {
+            row,
+            column: "id".into(),
+            expected: ValueKind::Int,
+            value: value.into(),
+        };
+        assert_eq!(
+            violations,
+            vec![
+                bad(1, "two"),
+                bad(3, "4.5"),
+                SchemaViolation::MissingColumn {
+                    column: "score".into(),
+                },
+            ]
+        );
+    }
+}
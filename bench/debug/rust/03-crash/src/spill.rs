@@ -0,0 +1,364 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::csv_parser::{scan_csv_lines, CsvOptions};
+use crate::processor::ParsedData;
+
+/// Distinguishes the spill files of one process.
+static NEXT_SPILL_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A parsed CSV table whose rows past a threshold live in a temporary
+/// file instead of in memory; see [`parse_csv_spilling`].
+///
+/// Rows are read back lazily, one seek per row.  Only the rows below
+/// the threshold and one file offset per spilled row stay in memory.
+/// The file is deleted when the table is dropped.
+///
+/// This is a separate type, not a [`ParsedData`] variant.  It offers
+/// the read-only `CsvTable` accessors ([`cell`](Self::cell),
+/// [`column`](Self::column), [`value_counts`](Self::value_counts),
+/// [`filter_rows`](Self::filter_rows), [`rows_range`](Self::rows_range)
+/// and row iteration), which stream the rows and so return `Err` if the
+/// spill file cannot be read.  Methods that rewrite a table need
+/// [`to_table`](Self::to_table), which loads every row.
+#[derive(Debug)]
+pub struct SpilledTable {
+    headers: Vec<String>,
+    in_memory: Vec<Vec<String>>,
+    spill: Option<Spill>,
+}
+
+#[derive(Debug)]
+struct Spill {
+    path: PathBuf,
+    file: Mutex<File>,
+    /// Byte offset of each spilled row in the file.
+    offsets: Vec<u64>,
+}
+
+/// Parse CSV from `lines` like [`crate::csv_parser::parse_csv_lines`],
+/// keeping at most `max_in_memory` rows in memory and spilling the rest
+/// to a temporary file.
+///
+/// Returns `Err` for the same malformed input as `parse_csv_lines`, or
+/// if the spill file cannot be written.
+pub fn parse_csv_spilling<I, S>(
+    lines: I,
+    options: &CsvOptions,
+    max_in_memory: usize,
+) -> Result<SpilledTable, String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut in_memory = Vec::new();
+    let mut writer: Option<(PathBuf, BufWriter<File>, Vec<u64>, u64)> = None;
+
+    let headers = scan_csv_lines(lines, options, |row| {
+        if in_memory.len() < max_in_memory {
+            in_memory.push(row);
+            return Ok(());
+        }
+        if writer.is_none() {
+            let (path, file) = create_spill_file().map_err(spill_err)?;
+            writer = Some((path, BufWriter::new(file), Vec::new(), 0));
+        }
+        if let Some((_, out, offsets, written)) = writer.as_mut() {
+            offsets.push(*written);
+            *written += write_row(out, &row).map_err(spill_err)?;
+        }
+        Ok(())
+    });
+
+    let spill = match writer {
+        Some((path, out, offsets, _)) => {
+            let spill = out
+                .into_inner()
+                .map_err(|err| err.into_error())
+                .map(|file| Spill {
+                    path: path.clone(),
+                    file: Mutex::new(file),
+                    offsets,
+                });
+            match spill {
+                Ok(spill) => Some(spill),
+                Err(err) => {
+                    let _ = fs::remove_file(&path);
+                    return Err(spill_err(err));
+                }
+            }
+        }
+        None => None,
+    };
+    // Build the table before checking `headers` so that a failed parse
+    // still removes the spill file on drop.
+    let mut table = SpilledTable {
+        headers: Vec::new(),
+        in_memory,
+        spill,
+    };
+    table.headers = headers?;
+    Ok(table)
+}
+
+impl SpilledTable {
+    pub fn headers(&self) -> &[String] {
+        &self.headers
+    }
+
+    /// Number of data rows, in memory and spilled.
+    pub fn len(&self) -> usize {
+        self.in_memory.len() + self.spill.as_ref().map_or(0, |s| s.offsets.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if any rows were spilled to disk.
+    pub fn is_spilled(&self) -> bool {
+        self.spill.is_some()
+    }
+
+    /// Data row `index`, or `None` if it is out of range.
+    pub fn row(&self, index: usize) -> io::Result<Option<Vec<String>>> {
+        if let Some(row) = self.in_memory.get(index) {
+            return Ok(Some(row.clone()));
+        }
+        let Some(spill) = &self.spill else {
+            return Ok(None);
+        };
+        let Some(&offset) = spill.offsets.get(index - self.in_memory.len()) else {
+            return Ok(None);
+        };
+        let mut file = spill.file.lock().unwrap();
+        file.seek(SeekFrom::Start(offset))?;
+        read_row(&mut *file).map(Some)
+    }
+
+    /// Every data row in order, read back lazily.
+    pub fn rows(&self) -> impl Iterator<Item = io::Result<Vec<String>>> + '_ {
+        (0..self.len()).filter_map(|i| self.row(i).transpose())
+    }
+
+    /// Every cell of column `header`, in row order.
+    ///
+    /// Returns `Err` for an unknown header or if reading the spill file
+    /// fails.
+    pub fn column(&self, header: &str) -> Result<Vec<String>, String> {
+        let col = self.column_index(header)?;
+        self.rows()
+            .map(|row| row.map(|mut row| row.swap_remove(col)).map_err(spill_err))
+            .collect()
+    }
+
+    /// Like [`ParsedData::cell`]: the value of column `header` in data
+    /// row `row`, or `None` for an out-of-range row or unknown header.
+    pub fn cell(&self, row: usize, header: &str) -> Result<Option<String>, String> {
+        let Some(col) = self.headers.iter().position(|h| h == header) else {
+            return Ok(None);
+        };
+        let row = self.row(row).map_err(spill_err)?;
+        Ok(row.and_then(|mut row| row.get_mut(col).map(std::mem::take)))
+    }
+
+    /// Like [`ParsedData::value_counts`].
+    pub fn value_counts(&self, header: &str) -> Result<HashMap<String, usize>, String> {
+        let col = self.column_index(header)?;
+        let mut counts = HashMap::new();
+        for row in self.rows() {
+            let mut row = row.map_err(spill_err)?;
+            let cell = row.get_mut(col).map(std::mem::take).unwrap_or_default();
+            *counts.entry(cell).or_insert(0) += 1;
+        }
+        Ok(counts)
+    }
+
+    /// Like [`ParsedData::distinct_count`].
+    pub fn distinct_count(&self, header: &str) -> Result<usize, String> {
+        self.value_counts(header).map(|counts| counts.len())
+    }
+
+    /// Like [`ParsedData::filter_rows`]; only the matching rows are
+    /// loaded into the returned in-memory `CsvTable`.
+    pub fn filter_rows(
+        &self,
+        header: &str,
+        pred: impl Fn(&str) -> bool,
+    ) -> Result<ParsedData, String> {
+        let col = self.column_index(header)?;
+        let mut rows = Vec::new();
+        for row in self.rows() {
+            let row = row.map_err(spill_err)?;
+            if row.get(col).is_some_and(|cell| pred(cell)) {
+                rows.push(row);
+            }
+        }
+        Ok(ParsedData::CsvTable {
+            headers: self.headers.clone(),
+            rows,
+        })
+    }
+
+    /// Like [`ParsedData::rows_range`]; only rows `start..end` are loaded.
+    pub fn rows_range(&self, start: usize, end: usize) -> Result<ParsedData, String> {
+        if start > end {
+            return Err(format!("Invalid row range {}..{}", start, end));
+        }
+        let end = end.min(self.len());
+        Ok(ParsedData::CsvTable {
+            headers: self.headers.clone(),
+            rows: (start.min(end)..end)
+                .filter_map(|i| self.row(i).transpose())
+                .collect::<io::Result<_>>()
+                .map_err(spill_err)?,
+        })
+    }
+
+    fn column_index(&self, header: &str) -> Result<usize, String> {
+        self.headers
+            .iter()
+            .position(|h| h == header)
+            .ok_or_else(|| format!("Unknown column {:?}", header))
+    }
+
+    /// Read every row back into an in-memory `CsvTable`.
+    pub fn to_table(&self) -> io::Result<ParsedData> {
+        Ok(ParsedData::CsvTable {
+            headers: self.headers.clone(),
+            rows: self.rows().collect::<io::Result<_>>()?,
+        })
+    }
+}
+
+impl Drop for SpilledTable {
+    fn drop(&mut self) {
+        if let Some(spill) = &self.spill {
+            let _ = fs::remove_file(&spill.path);
+        }
+    }
+}
+
+fn spill_err(err: io::Error) -> String {
+    format!("CSV spill file failed: {}", err)
+}
+
+fn create_spill_file() -> io::Result<(PathBuf, File)> {
+    let id = NEXT_SPILL_ID.fetch_add(1, Ordering::Relaxed);
+    let path = std::env::temp_dir().join(format!(
+        "parser-bench-spill-{}-{}.bin",
+        std::process::id(),
+        id
+    ));
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    Ok((path, file))
+}
+
+/// Write `row` as a field count followed by length-prefixed fields,
+/// returning the number of bytes written.
+fn write_row(out: &mut impl Write, row: &[String]) -> io::Result<u64> {
+    let mut written = 4;
+    out.write_all(&(row.len() as u32).to_le_bytes())?;
+    for field in row {
+        out.write_all(&(field.len() as u32).to_le_bytes())?;
+        out.write_all(field.as_bytes())?;
+        written += 4 + field.len() as u64;
+    }
+    Ok(written)
+}
+
+/// Read back one row written by [`write_row`].
+fn read_row(input: &mut impl Read) -> io::Result<Vec<String>> {
+    let read_u32 = |input: &mut dyn Read| -> io::Result<u32> {
+        let mut buf = [0u8; 4];
+        input.read_exact(&mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    };
+    let count = read_u32(input)?;
+    let mut row = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let len = read_u32(input)? as usize;
+        let mut bytes = vec![0u8; len];
+        input.read_exact(&mut bytes)?;
+        let field = String::from_utf8(bytes)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        row.push(field);
+    }
+    Ok(row)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csv_parser::parse_csv;
+
+    #[test]
+    fn rows_past_the_threshold_are_read_back_from_disk() {
+        let mut content = String::from("id,name\n");
+        for i in 0..10 {
+            content.push_str(&format!("{},\"row, {}\"\n", i, i));
+        }
+
+        let table = parse_csv_spilling(content.lines(), &CsvOptions::default(), 3).unwrap();
+        assert!(table.is_spilled());
+        assert_eq!(table.len(), 10);
+        assert_eq!(
+            table.row(1).unwrap(),
+            Some(vec!["1".into(), "row, 1".into()])
+        );
+        assert_eq!(
+            table.row(7).unwrap(),
+            Some(vec!["7".into(), "row, 7".into()])
+        );
+        assert_eq!(table.row(10).unwrap(), None);
+        assert_eq!(
+            table.column("id").unwrap(),
+            (0..10).map(|i| i.to_string()).collect::<Vec<_>>()
+        );
+        assert_eq!(table.to_table().unwrap(), parse_csv(&content).unwrap());
+
+        let path = table.spill.as_ref().unwrap().path.clone();
+        assert!(path.exists());
+        drop(table);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn table_accessors_match_an_in_memory_table() {
+        let content = "id,kind\n1,a\n2,b\n3,a\n4,c\n5,a\n";
+        let table = parse_csv_spilling(content.lines(), &CsvOptions::default(), 2).unwrap();
+        let expected = parse_csv(content).unwrap();
+        assert!(table.is_spilled());
+
+        assert_eq!(
+            table.cell(3, "kind").unwrap().as_deref(),
+            expected.cell(3, "kind")
+        );
+        assert_eq!(table.cell(9, "kind").unwrap(), None);
+        assert_eq!(table.cell(0, "nope").unwrap(), None);
+        assert_eq!(table.value_counts("kind"), expected.value_counts("kind"));
+        assert_eq!(table.distinct_count("kind"), Ok(3));
+        assert_eq!(
+            table.filter_rows("kind", |k| k == "a"),
+            expected.filter_rows("kind", |k| k == "a")
+        );
+        assert_eq!(table.rows_range(1, 4), expected.rows_range(1, 4));
+        assert!(table.value_counts("nope").is_err());
+    }
+
+    #[test]
+    fn small_tables_stay_in_memory() {
+        let table = parse_csv_spilling(["a,b", "1,2"], &CsvOptions::default(), 3).unwrap();
+        assert!(!table.is_spilled());
+        assert_eq!(table.rows().count(), 1);
+        assert!(parse_csv_spilling(["a,b", "1"], &CsvOptions::default(), 0).is_err());
+    }
+}
@@ -0,0 +1,51 @@
+/// Returns `true` for characters trimmed from the ends of keys and
+/// fields.
+///
+/// This is Unicode whitespace (so U+00A0 and the full-width U+3000 are
+/// included) plus the zero-width space, word joiner and byte-order mark,
+/// which are invisible but not classed as whitespace.
+pub fn is_blank(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '\u{200B}' | '\u{2060}' | '\u{FEFF}')
+}
+
+/// Trim [`is_blank`] characters from both ends of `s`.
+pub fn trim(s: &str) -> &str {
+    s.trim_matches(is_blank)
+}
+
+/// Line terminator written by the serializers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    /// `\r\n`, for output destined for Windows tools.
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+
+    /// `s` with every line break (`\n`, `\r\n` or a lone `\r`) replaced
+    /// by this ending.
+    pub fn normalize(self, s: &str) -> String {
+        s.replace("\r\n", "\n")
+            .replace('\r', "\n")
+            .replace('\n', self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trims_invisible_and_wide_spaces() {
+        assert_eq!(trim("\u{FEFF}\u{00A0}key\u{3000}"), "key");
+        assert_eq!(trim("\u{200B} a b \u{2060}"), "a b");
+    }
+}
@@ -0,0 +1,318 @@
+use crate::csv_parser::CsvOptions;
+use crate::processor::ParsedData;
+use crate::text;
+
+/// Decimal and thousands separators used when reading numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NumericLocale {
+    /// `1,234.56`: `.` decimal separator, `,` thousands separator.
+    #[default]
+    Us,
+    /// `1.234,56`: `,` decimal separator, `.` thousands separator.
+    European,
+}
+
+impl NumericLocale {
+    fn separators(self) -> (char, char) {
+        match self {
+            NumericLocale::Us => ('.', ','),
+            NumericLocale::European => (',', '.'),
+        }
+    }
+}
+
+/// The inferred type of a CSV column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    /// Every cell is empty or a null token.
+    Empty,
+    /// Every non-null cell is an integer.
+    Int,
+    /// Every non-null cell is a number, at least one with a fraction.
+    Float,
+    /// At least one non-null cell is not a number.
+    Text,
+}
+
+/// Summary statistics over the numeric cells of a column.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnStats {
+    /// Number of non-null cells.
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+/// A number read from a cell, remembering whether it was integral.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+impl Number {
+    pub fn as_f64(self) -> f64 {
+        match self {
+            Number::Int(i) => i as f64,
+            Number::Float(f) => f,
+        }
+    }
+}
+
+/// Read `value` as a number using `locale`'s separators.
+///
+/// Thousands separators are only accepted in well-formed groups of
+/// three digits, so `1,2` is not read as `12` under the US locale.
+pub fn parse_number(value: &str, locale: NumericLocale) -> Option<Number> {
+    let (decimal, thousands) = locale.separators();
+    let value = text::trim(value);
+
+    let (sign, unsigned) = match value.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", value.strip_prefix('+').unwrap_or(value)),
+    };
+
+    let (int_part, frac_part) = match unsigned.split_once(decimal) {
+        Some((i, f)) => (i, Some(f)),
+        None => (unsigned, None),
+    };
+
+    let groups: Vec<&str> = int_part.split(thousands).collect();
+    let grouped_ok = groups.len() == 1
+        || (!groups[0].is_empty()
+            && groups[0].len() <= 3
+            && groups[1..].iter().all(|g| g.len() == 3));
+    let digits_ok = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+
+    if int_part.is_empty() || !grouped_ok || !groups.iter().all(|g| digits_ok(g)) {
+        return None;
+    }
+
+    let int_digits: String = groups.concat();
+    match frac_part {
+        None => format!("{}{}", sign, int_digits)
+            .parse()
+            .ok()
+            .map(Number::Int),
+        Some(frac) if !frac.is_empty() && digits_ok(frac) => {
+            format!("{}{}.{}", sign, int_digits, frac)
+                .parse()
+                .ok()
+                .map(Number::Float)
+        }
+        Some(_) => None,
+    }
+}
+
+/// Infer the type of a column from its cell values.
+pub fn infer_column_type<'a, I>(values: I, options: &CsvOptions) -> ColumnType
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut inferred = ColumnType::Empty;
+
+    for value in values.into_iter().filter(|v| !options.is_null(v)) {
+        let cell = match parse_number(value, options.numeric_locale) {
+            Some(Number::Int(_)) => ColumnType::Int,
+            Some(Number::Float(_)) => ColumnType::Float,
+            None => return ColumnType::Text,
+        };
+        inferred = match (inferred, cell) {
+            (ColumnType::Empty, cell) => cell,
+            (ColumnType::Int, ColumnType::Int) => ColumnType::Int,
+            _ => ColumnType::Float,
+        };
+    }
+
+    inferred
+}
+
+impl ParsedData {
+    /// Infer the type of every column of a `CsvTable`, in header order.
+    pub fn column_types(&self, options: &CsvOptions) -> Result<Vec<ColumnType>, String> {
+        match self {
+            ParsedData::CsvTable { headers, rows } => Ok((0..headers.len())
+                .map(|col| {
+                    infer_column_type(
+                        rows.iter().map(|r| r.get(col).map_or("", String::as_str)),
+                        options,
+                    )
+                })
+                .collect()),
+            _ => Err("column_types is only supported on CsvTable".into()),
+        }
+    }
+
+    /// Compute [`ColumnStats`] for the numeric column `header`.
+    ///
+    /// Returns `Err` for non-tables, unknown headers, and columns that
+    /// are not numeric.
+    pub fn column_stats(&self, header: &str, options: &CsvOptions) -> Result<ColumnStats, String> {
+        let (headers, rows) = match self {
+            ParsedData::CsvTable { headers, rows } => (headers, rows),
+            _ => return Err("column_stats is only supported on CsvTable".into()),
+        };
+        let col = headers
+            .iter()
+            .position(|h| h == header)
+            .ok_or_else(|| format!("Unknown column {:?}", header))?;
+
+        let mut numbers = Vec::new();
+        for value in rows
+            .iter()
+            .map(|r| r.get(col).map_or("", |v| text::trim(v)))
+            .filter(|v| !options.is_null(v))
+        {
+            let number = parse_number(value, options.numeric_locale)
+                .ok_or_else(|| format!("Column {:?} is not numeric: {:?}", header, value))?;
+            numbers.push(number.as_f64());
+        }
+
+        if numbers.is_empty() {
+            return Err(format!("Column {:?} has no values", header));
+        }
+
+        Ok(ColumnStats {
+            count: numbers.len(),
+            min: numbers.iter().copied().fold(f64::INFINITY, f64::min),
+            max: numbers.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            mean: numbers.iter().sum::<f64>() / numbers.len() as f64,
+        })
+    }
+
+    /// Returns `true` if column `header` of a `CsvTable` is in
+    /// non-decreasing order.
+    ///
+    /// A numeric column (see [`infer_column_type`], with default
+    /// options) is compared by value, skipping empty cells; any other
+    /// column is compared lexicographically.  Returns `Err` for an
+    /// unknown header or a non-table variant.
+    pub fn is_sorted_by(&self, header: &str) -> Result<bool, String> {
+        let (headers, rows) = match self {
+            ParsedData::CsvTable { headers, rows } => (headers, rows),
+            _ => return Err("is_sorted_by is only supported on CsvTable".into()),
+        };
+        let col = headers
+            .iter()
+            .position(|h| h == header)
+            .ok_or_else(|| format!("Unknown column {:?}", header))?;
+        let values: Vec<&str> = rows
+            .iter()
+            .map(|r| r.get(col).map_or("", String::as_str))
+            .collect();
+
+        let options = CsvOptions::default();
+        let sorted = match infer_column_type(values.iter().copied(), &options) {
+            ColumnType::Text => values.windows(2).all(|w| w[0] <= w[1]),
+            _ => {
+                let numbers: Vec<f64> = values
+                    .iter()
+                    .filter(|v| !options.is_null(v))
+                    .filter_map(|v| parse_number(v, options.numeric_locale))
+                    .map(Number::as_f64)
+                    .collect();
+                numbers.windows(2).all(|w| w[0] <= w[1])
+            }
+        };
+        Ok(sorted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csv_parser::parse_csv_with;
+
+    #[test]
+    fn european_float_is_inferred_under_european_locale() {
+        let options = CsvOptions {
+            numeric_locale: NumericLocale::European,
+            ..CsvOptions::default()
+        };
+        let data =
+            parse_csv_with("item,price\nwidget,\"1.234,56\"\ngadget,\"2,5\"", &options).unwrap();
+        assert_eq!(
+            data.column_types(&options).unwrap(),
+            vec![ColumnType::Text, ColumnType::Float]
+        );
+        let stats = data.column_stats("price", &options).unwrap();
+        assert_eq!(stats.count, 2);
+        assert!((stats.max - 1234.56).abs() < 1e-9);
+
+        // The stored value is untouched.
+        assert_eq!(data.cell(0, "price"), Some("1.234,56"));
+
+        // Under the default locale the same value is text.
+        assert_eq!(
+            data.column_types(&CsvOptions::default()).unwrap()[1],
+            ColumnType::Text
+        );
+    }
+
+    #[test]
+    fn us_formatting_works_under_default_locale() {
+        let options = CsvOptions::default();
+        let data = parse_csv_with("id,price\n1,\"1,234.5\"\n2,10", &options).unwrap();
+        assert_eq!(
+            data.column_types(&options).unwrap(),
+            vec![ColumnType::Int, ColumnType::Float]
+        );
+        let stats = data.column_stats("price", &options).unwrap();
+        assert!((stats.mean - 622.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn null_tokens_are_ignored_for_inference() {
+        let content = "id,count\n1,10\n2,NA\n3,-\n4,7";
+        let options = CsvOptions {
+            null_tokens: vec!["NA".into(), "NULL".into(), "-".into()],
+            ..CsvOptions::default()
+        };
+        let data = parse_csv_with(content, &options).unwrap();
+        assert_eq!(data.column_types(&options).unwrap()[1], ColumnType::Int);
+        assert_eq!(data.column_stats("count", &options).unwrap().count, 2);
+        assert_eq!(data.cell(1, "count"), Some("NA"));
+
+        // Without null tokens the column is text.
+        assert_eq!(
+            data.column_types(&CsvOptions::default()).unwrap()[1],
+            ColumnType::Text
+        );
+
+        let blanked = CsvOptions {
+            blank_null_tokens: true,
+            ..options
+        };
+        let data = parse_csv_with(content, &blanked).unwrap();
+        assert_eq!(data.cell(1, "count"), Some(""));
+    }
+
+    #[test]
+    fn malformed_grouping_is_not_a_number() {
+        assert_eq!(parse_number("1,2", NumericLocale::Us), None);
+        assert_eq!(
+            parse_number("12,345", NumericLocale::Us),
+            Some(Number::Int(12345))
+        );
+        assert_eq!(
+            parse_number("-0,5", NumericLocale::European),
+            Some(Number::Float(-0.5))
+        );
+    }
+
+    #[test]
+    fn numeric_columns_are_checked_for_sorting_by_value() {
+        let data = parse_csv_with("id,name\n2,bob\n10,alice\n10,carol\n", &CsvOptions::default())
+            .unwrap();
+        assert_eq!(data.is_sorted_by("id"), Ok(true));
+        assert_eq!(data.is_sorted_by("name"), Ok(false));
+        assert!(data.is_sorted_by("email").is_err());
+    }
+
+    #[test]
+    fn unsorted_numeric_column_is_reported() {
+        let data = parse_csv_with("n\n1\n3\n2\n", &CsvOptions::default()).unwrap();
+        assert_eq!(data.is_sorted_by("n"), Ok(false));
+    }
+}
@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+
+use crate::error::source_snippet;
+use crate::processor::{ParsedData, Span};
+use crate::text::{self, is_blank};
+
+/// Parse a minimal YAML-style indented key-value document.
+///
+/// Only the subset used for simple config files is supported:
+///
+/// ```text
+/// name: test_app
+/// network:
+///   port: 8080
+///   timeout: 30
+/// ```
+///
+/// A top-level `key:` with no value opens a block, and the indented
+/// `subkey: value` lines beneath it are stored as `key.subkey`.  Only
+/// scalar values and one level of nesting are supported.  Lines
+/// starting with `#` are comments.
+///
+/// Returns `Err` for lines without a `:` or for nesting deeper than
+/// one level.
+pub fn parse_yaml_lite(content: &str) -> Result<ParsedData, String> {
+    let mut map = HashMap::new();
+    let mut current_block: Option<String> = None;
+
+    for (line_no, raw) in content.lines().enumerate() {
+        let line = text::trim(raw);
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fail = |msg: String| {
+            let offset = Span::of(content, raw).offset;
+            format!("Line {}: {}\n{}", line_no + 1, msg, source_snippet(content, offset))
+        };
+
+        let (key, value) = line
+            .split_once(':')
+            .map(|(k, v)| (text::trim(k), text::trim(v)))
+            .ok_or_else(|| fail(format!("expected `key: value`, got {:?}", raw)))?;
+
+        let indented = raw.starts_with(is_blank);
+
+        if !indented {
+            if value.is_empty() {
+                current_block = Some(key.to_string());
+            } else {
+                current_block = None;
+                map.insert(key.to_string(), value.to_string());
+            }
+            continue;
+        }
+
+        let block = current_block
+            .as_ref()
+            .ok_or_else(|| fail("indented entry outside of a block".into()))?;
+
+        if value.is_empty() {
+            return Err(fail(format!(
+                "nested block {:?} is not supported (only one level of nesting)",
+                key
+            )));
+        }
+
+        map.insert(format!("{}.{}", block, key), value.to_string());
+    }
+
+    Ok(ParsedData::Config(map))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn two_level_block() {
+        let input = "name: test_app\nnetwork:\n  port: 8080\n  timeout: 30\n";
+        match parse_yaml_lite(input).unwrap() {
+            ParsedData::Config(map) => {
+                assert_eq!(map.len(), 3);
+                assert_eq!(map["name"], "test_app");
+                assert_eq!(map["network.port"], "8080");
+                assert_eq!(map["network.timeout"], "30");
+            }
+            other => panic!("expected Config, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn deeper_nesting_is_rejected() {
+        let input = "a:\n  b:\n    c: 1\n";
+        let err = parse_yaml_lite(input).unwrap_err();
+        assert!(err.starts_with("Line 2: nested block"), "{}", err);
+        assert!(err.contains("> 2 |   b:"), "{}", err);
+    }
+}
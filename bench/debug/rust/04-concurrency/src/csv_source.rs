@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+
+use parser_bench::processor::ParsedData;
+
+use crate::worker::Record;
+
+/// Turn the rows of a parsed `CsvTable` into pipeline [`Record`]s.
+///
+/// `id_column` supplies each record's id and the full row, joined with
+/// commas, becomes its payload.  The result can be fed straight to
+/// [`crate::pipeline::run_pipeline_from`].
+///
+/// Returns `Err` if `table` is not a `CsvTable`, `id_column` is not one
+/// of its headers, or any id is not a valid `u32`.
+pub fn table_records<'a>(
+    table: &'a ParsedData,
+    id_column: &str,
+) -> Result<impl Iterator<Item = Record> + 'a, String> {
+    let (headers, rows) = match table {
+        ParsedData::CsvTable { headers, rows } => (headers, rows),
+        _ => return Err("expected a CsvTable".into()),
+    };
+    let col = headers
+        .iter()
+        .position(|h| h == id_column)
+        .ok_or_else(|| format!("Unknown id column {:?}", id_column))?;
+
+    let ids = rows
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let raw = row.get(col).map_or("", String::as_str);
+            raw.parse::<u32>()
+                .map_err(|_| format!("Row {}: id {:?} is not a valid u32", i + 1, raw))
+        })
+        .collect::<Result<Vec<u32>, String>>()?;
+
+    Ok(ids
+        .into_iter()
+        .zip(rows)
+        .map(|(id, row)| Record::with_payload(id, row.join(","))))
+}
+
+/// Collect the rows of a parsed `CsvTable` into [`Record`]s, like
+/// [`table_records`], rejecting tables whose ids are not unique.
+///
+/// Returns `Err` naming both rows (1-based) for the first duplicated id,
+/// in addition to the errors [`table_records`] reports.
+pub fn records_from_table(table: &ParsedData, id_column: &str) -> Result<Vec<Record>, String> {
+    let records: Vec<Record> = table_records(table, id_column)?.collect();
+
+    let mut first_row = HashMap::new();
+    for (i, record) in records.iter().enumerate() {
+        if let Some(prev) = first_row.insert(record.id, i) {
+            return Err(format!(
+                "Duplicate id {} in rows {} and {}",
+                record.id,
+                prev + 1,
+                i + 1
+            ));
+        }
+    }
+
+    Ok(records)
+}
+
+/// Turn finished records into a `CsvTable`, one row per record in the
+/// given order, for writing run results out for analysis.
+///
+/// The columns are `id`, `batch_id`, `stage`, `retries`, `checksum` and
+/// `payload`.
+pub fn results_to_table(results: &[Record]) -> ParsedData {
+    let headers = ["id", "batch_id", "stage", "retries", "checksum", "payload"]
+        .map(String::from)
+        .to_vec();
+    let rows = results
+        .iter()
+        .map(|r| {
+            vec![
+                r.id.to_string(),
+                r.batch_id.to_string(),
+                r.stage.to_string(),
+                r.retry_count.to_string(),
+                r.checksum.to_string(),
+                r.payload.clone(),
+            ]
+        })
+        .collect();
+    ParsedData::CsvTable { headers, rows }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser_bench::csv_parser::parse_csv;
+
+    #[test]
+    fn maps_the_chosen_id_column() {
+        let table = parse_csv("name,id\nalpha,7\nbeta,3\n").unwrap();
+        let records = records_from_table(&table, "id").unwrap();
+        let ids: Vec<u32> = records.iter().map(|r| r.id).collect();
+        assert_eq!(ids, vec![7, 3]);
+        assert_eq!(records[0].payload, "alpha,7");
+    }
+
+    #[test]
+    fn non_numeric_id_names_the_row() {
+        let table = parse_csv("id,name\n1,alpha\nx2,beta\n").unwrap();
+        let err = records_from_table(&table, "id").unwrap_err();
+        assert_eq!(err, "Row 2: id \"x2\" is not a valid u32");
+    }
+
+    #[test]
+    fn duplicate_ids_are_reported() {
+        let table = parse_csv("id\n1\n2\n1\n").unwrap();
+        let err = records_from_table(&table, "id").unwrap_err();
+        assert_eq!(err, "Duplicate id 1 in rows 1 and 3");
+    }
+
+    #[test]
+    fn results_become_a_table() {
+        let mut retried = Record::new(2);
+        retried.mark_retry();
+        let table = results_to_table(&[Record::new(1), retried]);
+
+        match &table {
+            ParsedData::CsvTable { headers, rows } => {
+                assert_eq!(
+                    headers,
+                    &["id", "batch_id", "stage", "retries", "checksum", "payload"]
+                );
+                assert_eq!(rows.len(), 2);
+            }
+            other => panic!("expected CsvTable, got {:?}", other),
+        }
+        assert_eq!(table.cell(1, "retries"), Some("1"));
+        assert_eq!(table.cell(0, "payload"), Some("data-0001"));
+    }
+}
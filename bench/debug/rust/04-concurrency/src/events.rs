@@ -0,0 +1,130 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Callbacks on the state transitions of a pipeline run, for bridging
+/// to an external tracing or metrics system.  Set one with
+/// [`PipelineConfig::observer`].
+///
+/// Every method defaults to a no-op, so an observer only implements
+/// the ones it cares about.  They are called inline on the pipeline's
+/// threads, often several at once, and should return quickly.
+///
+/// [`PipelineConfig::observer`]: crate::pipeline::PipelineConfig::observer
+pub trait PipelineObserver: fmt::Debug {
+    /// The producer handed record `id` to Stage 1.
+    fn on_record_produced(&self, _id: u32) {}
+
+    /// Stage `stage` (1-3) started on record `id`, including repeat
+    /// visits after a feedback bounce.
+    fn on_stage_enter(&self, _stage: u8, _id: u32) {}
+
+    /// Stage 3 collected `record`.
+    fn on_record_completed(&self, _record: &crate::worker::Record) {}
+
+    /// Record `id` was discarded instead of completing: dropped by the
+    /// producer (`drop_rate`) or mapped to nothing by `fan_out`.
+    fn on_record_dead_lettered(&self, _id: u32) {}
+}
+
+/// Settings for the opt-in event log of a pipeline run.
+#[derive(Debug, Clone, Copy)]
+pub struct EventLogConfig {
+    /// Maximum number of per-record events kept; later ones are
+    /// discarded.  The start and end events are always kept.
+    pub capacity: usize,
+    /// Only records whose id is a multiple of this are traced (`1`
+    /// traces every record, `0` none).
+    pub sample_every: u32,
+}
+
+impl Default for EventLogConfig {
+    fn default() -> Self {
+        EventLogConfig {
+            capacity: 1024,
+            sample_every: 10,
+        }
+    }
+}
+
+/// What happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    ProducerStart,
+    /// Record `id` was received by stage `stage` (1-3).
+    Enter { stage: u8, id: u32 },
+    /// Record `id` was handed on by stage `stage` (1-3).
+    Leave { stage: u8, id: u32 },
+    PipelineEnd,
+}
+
+/// One entry of the event log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Event {
+    /// Time since the run started.
+    pub at: Duration,
+    pub kind: EventKind,
+}
+
+/// Shared, bounded event log.  The default recorder is disabled and
+/// ignores every event.
+#[derive(Debug, Clone, Default)]
+pub struct EventRecorder(Option<Arc<RecorderState>>);
+
+#[derive(Debug)]
+struct RecorderState {
+    start: Instant,
+    config: EventLogConfig,
+    events: Mutex<Vec<Event>>,
+}
+
+impl EventRecorder {
+    /// A recorder timing events relative to `start`.
+    pub fn new(config: EventLogConfig, start: Instant) -> Self {
+        EventRecorder(Some(Arc::new(RecorderState {
+            start,
+            config,
+            events: Mutex::new(Vec::new()),
+        })))
+    }
+
+    pub fn enter(&self, stage: u8, id: u32) {
+        self.record_sampled(id, EventKind::Enter { stage, id });
+    }
+
+    pub fn leave(&self, stage: u8, id: u32) {
+        self.record_sampled(id, EventKind::Leave { stage, id });
+    }
+
+    /// Record a run-level event, bypassing sampling and the capacity.
+    pub fn record(&self, kind: EventKind) {
+        if let Some(state) = &self.0 {
+            let at = state.start.elapsed();
+            state.events.lock().unwrap().push(Event { at, kind });
+        }
+    }
+
+    /// The events recorded so far, in the order they were recorded.
+    pub fn snapshot(&self) -> Vec<Event> {
+        match &self.0 {
+            Some(state) => state.events.lock().unwrap().clone(),
+            None => Vec::new(),
+        }
+    }
+
+    fn record_sampled(&self, id: u32, kind: EventKind) {
+        let Some(state) = &self.0 else {
+            return;
+        };
+        let every = state.config.sample_every;
+        if every == 0 || !id.is_multiple_of(every) {
+            return;
+        }
+        let at = state.start.elapsed();
+        let mut events = state.events.lock().unwrap();
+        // The start event does not count towards the capacity.
+        if events.len() <= state.config.capacity {
+            events.push(Event { at, kind });
+        }
+    }
+}
@@ -0,0 +1,10 @@
+//! Three-stage record pipeline with a feedback loop between stages 1
+//! and 2.
+
+pub mod csv_source;
+pub mod events;
+pub mod metrics;
+pub mod pipeline;
+pub mod rng;
+pub mod stage;
+pub mod worker;
@@ -1,42 +1,34 @@
-mod worker;
-mod stage;
-mod pipeline;
-
-use std::sync::mpsc;
-use std::thread;
 use std::time::Duration;
 
-use worker::{check_completeness, validate_batch};
+use pipeline_bench::pipeline::{self, PipelineConfig, RunOutcome};
+use pipeline_bench::worker::{self, check_completeness, validate_batch};
 
 /// Run the pipeline with a timeout.
 ///
 /// If the pipeline completes within the timeout, print a summary of the
-/// results.  If it hangs, print an error and exit.
+/// results.  If it hangs, report whatever it had collected so far and
+/// exit with an error.
 fn main() {
-    let (result_tx, result_rx) = mpsc::channel();
-
-    let handle = thread::spawn(move || {
-        let results = pipeline::run_pipeline();
-        let _ = result_tx.send(results);
-    });
+    let running = pipeline::spawn_pipeline(PipelineConfig::default());
 
     // Wait up to 5 seconds.
-    match result_rx.recv_timeout(Duration::from_secs(5)) {
-        Ok(results) => {
-            report_results(&results);
+    match running.wait_timeout(Duration::from_secs(5)) {
+        RunOutcome::Finished(Ok(output)) => {
+            report_results(&output.results);
         }
-        Err(mpsc::RecvTimeoutError::Timeout) => {
-            eprintln!("ERROR: Pipeline timed out after 5s");
+        RunOutcome::Finished(Err(err)) => {
+            eprintln!("ERROR: {}", err);
             std::process::exit(1);
         }
-        Err(mpsc::RecvTimeoutError::Disconnected) => {
-            eprintln!("ERROR: Pipeline thread disconnected unexpectedly");
+        RunOutcome::TimedOut(partial) => {
+            eprintln!(
+                "ERROR: Pipeline timed out after 5s; reporting {} partial results",
+                partial.len()
+            );
+            report_results(&partial);
             std::process::exit(1);
         }
     }
-
-    // Join if we got a result.
-    let _ = handle.join();
 }
 
 /// Print a summary of the pipeline output.
@@ -56,12 +48,13 @@ fn report_results(results: &[worker::Record]) {
     }
 
     // Completeness check.
-    let (missing, duplicates) = check_completeness(results, 500);
+    let report = check_completeness(results, 500);
+    let (missing, duplicates) = (&report.missing, &report.duplicates);
     if !missing.is_empty() {
         eprintln!(
-            "WARNING: {} missing record ids: {:?}",
+            "WARNING: {} missing record ids in ranges: {:?}",
             missing.len(),
-            &missing[..missing.len().min(10)]
+            &report.gaps[..report.gaps.len().min(10)]
         );
     }
     if !duplicates.is_empty() {
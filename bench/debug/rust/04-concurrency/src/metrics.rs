@@ -0,0 +1,225 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Throughput figures for one pipeline run, excluding warm-up records.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Metrics {
+    /// Records that completed after the warm-up and were timed.
+    pub measured_records: u32,
+    /// Time from the end of the warm-up (or the start of the run, if
+    /// there was none) to the last completion.
+    pub elapsed: Duration,
+}
+
+impl Metrics {
+    /// Measured records per second, or 0 if nothing was timed.
+    pub fn throughput(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            f64::from(self.measured_records) / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Shared recorder that Stage 3 notifies on every completed record.
+///
+/// The first `warmup_records` completions only start the clock; the
+/// rest are counted in [`Metrics`].
+#[derive(Debug, Clone)]
+pub struct MetricsRecorder(Arc<Mutex<RecorderState>>);
+
+#[derive(Debug)]
+struct RecorderState {
+    warmup_records: u32,
+    completed: u32,
+    started: Option<Instant>,
+    last: Option<Instant>,
+}
+
+impl MetricsRecorder {
+    pub fn new(warmup_records: u32) -> Self {
+        MetricsRecorder(Arc::new(Mutex::new(RecorderState {
+            warmup_records,
+            completed: 0,
+            started: (warmup_records == 0).then(Instant::now),
+            last: None,
+        })))
+    }
+
+    /// Note that one more record has completed.
+    pub fn record_completion(&self) {
+        let mut state = self.0.lock().unwrap();
+        let now = Instant::now();
+        state.completed += 1;
+        if state.completed == state.warmup_records {
+            state.started = Some(now);
+        } else if state.completed > state.warmup_records {
+            state.last = Some(now);
+        }
+    }
+
+    /// Metrics for the completions recorded so far.
+    pub fn snapshot(&self) -> Metrics {
+        let state = self.0.lock().unwrap();
+        let elapsed = match (state.started, state.last) {
+            (Some(started), Some(last)) => last - started,
+            _ => Duration::ZERO,
+        };
+        Metrics {
+            measured_records: state.completed.saturating_sub(state.warmup_records),
+            elapsed,
+        }
+    }
+}
+
+impl Default for MetricsRecorder {
+    fn default() -> Self {
+        MetricsRecorder::new(0)
+    }
+}
+
+/// One of the pipeline's forward channels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Queue {
+    /// Producer to Stage 1.
+    Input = 0,
+    Stage1ToStage2 = 1,
+    Stage2ToStage3 = 2,
+}
+
+/// Cumulative send and receive counts on each forward channel, from
+/// which approximate queue depths are derived.
+#[derive(Debug, Clone, Default)]
+pub struct QueueCounters(Arc<QueueCounts>);
+
+#[derive(Debug, Default)]
+struct QueueCounts {
+    sent: [AtomicU64; 3],
+    received: [AtomicU64; 3],
+}
+
+impl QueueCounters {
+    /// Note a record sent into `queue`.
+    pub fn sent(&self, queue: Queue) {
+        self.0.sent[queue as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Note a record taken out of `queue`.
+    pub fn received(&self, queue: Queue) {
+        self.0.received[queue as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records sent into `queue` but not yet received.  Approximate
+    /// while the pipeline is running, since the two counts are read
+    /// separately.
+    pub fn depth(&self, queue: Queue) -> u64 {
+        let received = self.0.received[queue as usize].load(Ordering::Relaxed);
+        let sent = self.0.sent[queue as usize].load(Ordering::Relaxed);
+        sent.saturating_sub(received)
+    }
+}
+
+/// Warns once per forward channel when a send into it blocks for longer
+/// than a threshold, as a cheap hint that `channel_bound` is too small.
+/// Disabled by default.
+#[derive(Debug, Clone, Default)]
+pub struct BackpressureMonitor(Option<Arc<MonitorState>>);
+
+#[derive(Debug)]
+struct MonitorState {
+    threshold: Duration,
+    /// Sends that blocked past the threshold, on any channel.
+    blocked: AtomicU64,
+    warned: [AtomicBool; 3],
+    order: Mutex<Vec<Queue>>,
+}
+
+impl BackpressureMonitor {
+    pub fn new(threshold: Duration) -> Self {
+        BackpressureMonitor(Some(Arc::new(MonitorState {
+            threshold,
+            blocked: AtomicU64::new(0),
+            warned: Default::default(),
+            order: Mutex::new(Vec::new()),
+        })))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// Note that a send into `queue` blocked for `blocked`, warning on
+    /// stderr the first time this exceeds the threshold.
+    pub fn observe(&self, queue: Queue, blocked: Duration) {
+        let Some(state) = &self.0 else {
+            return;
+        };
+        if blocked <= state.threshold {
+            return;
+        }
+        state.blocked.fetch_add(1, Ordering::Relaxed);
+        if !state.warned[queue as usize].swap(true, Ordering::SeqCst) {
+            eprintln!(
+                "[backpressure] send into {:?} blocked for {:?} (threshold {:?}); \
+                 consider a larger channel_bound",
+                queue, blocked, state.threshold
+            );
+            state.order.lock().unwrap().push(queue);
+        }
+    }
+
+    /// Number of sends so far that blocked past the threshold.
+    pub fn blocked_sends(&self) -> u64 {
+        self.0
+            .as_ref()
+            .map_or(0, |state| state.blocked.load(Ordering::Relaxed))
+    }
+
+    /// The channels warned about so far, in the order they were.
+    pub fn warned(&self) -> Vec<Queue> {
+        match &self.0 {
+            Some(state) => state.order.lock().unwrap().clone(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Approximate queue depths at one point of a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueDepthSample {
+    /// Time since the run started.
+    pub at: Duration,
+    pub input: u64,
+    pub stage1_to_stage2: u64,
+    pub stage2_to_stage3: u64,
+}
+
+/// Sample the depths in `counters` every `interval` until `done` is
+/// set, taking one sample up front and one after `done`.
+pub fn sample_queue_depths(
+    counters: &QueueCounters,
+    interval: Duration,
+    start: Instant,
+    done: &AtomicBool,
+) -> Vec<QueueDepthSample> {
+    let sample = || QueueDepthSample {
+        at: start.elapsed(),
+        input: counters.depth(Queue::Input),
+        stage1_to_stage2: counters.depth(Queue::Stage1ToStage2),
+        stage2_to_stage3: counters.depth(Queue::Stage2ToStage3),
+    };
+
+    let mut series = vec![sample()];
+    while !done.load(Ordering::SeqCst) {
+        thread::sleep(interval);
+        if !done.load(Ordering::SeqCst) {
+            series.push(sample());
+        }
+    }
+    series.push(sample());
+    series
+}
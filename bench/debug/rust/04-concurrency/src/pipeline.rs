@@ -1,8 +1,20 @@
-use std::sync::mpsc::sync_channel;
-use std::thread;
+use std::collections::HashMap;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, SyncSender, TryRecvError, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, sync_channel};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
-use crate::stage;
-use crate::worker::Record;
+use crate::events::{Event, EventKind, EventLogConfig, EventRecorder, PipelineObserver};
+use crate::metrics::{
+    self, BackpressureMonitor, Metrics, MetricsRecorder, Queue, QueueDepthSample,
+};
+use crate::rng::Rng;
+use crate::stage::{self, CancelToken, FanOut, PartialResults, StageContext};
+use crate::worker::{Record, StageId};
 
 /// Channel buffer size.
 const CHANNEL_BOUND: usize = 5;
@@ -10,10 +22,123 @@ const CHANNEL_BOUND: usize = 5;
 /// Total records to push through the pipeline.
 const NUM_RECORDS: u32 = 500;
 
-/// Configuration for the pipeline (extracted for clarity).
-struct PipelineConfig {
-    num_records: u32,
-    channel_bound: usize,
+/// Number of stages the pipeline is wired with.
+pub const STAGE_COUNT: usize = 3;
+
+/// Configuration for the pipeline.
+#[derive(Debug, Clone)]
+pub struct PipelineConfig {
+    pub num_records: u32,
+    pub channel_bound: usize,
+    /// Maximum number of records per second the producer feeds into
+    /// Stage 1.  `None` (or `Some(0)`) means unlimited.
+    pub rate_limit: Option<u32>,
+    /// Audit sink that Stage 3 sends a copy of every completed record
+    /// to, in addition to collecting it.  A full sink blocks Stage 3.
+    pub tee: Option<SyncSender<Record>>,
+    /// Wall-clock cap on the run.  When exceeded the pipeline is
+    /// cancelled and returns the results collected so far.
+    pub max_runtime: Option<Duration>,
+    /// Artificial per-record delay in Stage 2, for simulating a slow
+    /// transformation.
+    pub stage2_delay: Option<Duration>,
+    /// Give up on a record if Stage 1's input channel stays full for
+    /// the whole retry budget, failing the run with
+    /// [`PipelineError::SendFailed`].  `None` (the default) blocks until
+    /// there is room, applying backpressure to the producer.
+    pub send_retry: Option<RetryPolicy>,
+    /// Fraction of records, in `[0, 1]`, the producer deliberately drops
+    /// instead of sending, for chaos testing.  See
+    /// [`PipelineOutput::dropped`].
+    pub drop_rate: f32,
+    /// Seed for the pseudo-random choices made by the run.
+    pub seed: u64,
+    /// Number of leading completions excluded from
+    /// [`PipelineOutput::metrics`].  Warm-up records are still processed
+    /// and collected.
+    pub warmup_records: u32,
+    /// Record a sampled event log, returned in
+    /// [`PipelineOutput::events`].  `None` disables it.
+    pub event_log: Option<EventLogConfig>,
+    /// Sample the forward channels' queue depths at this interval,
+    /// returned in [`PipelineOutput::queue_depths`].  `None` disables
+    /// sampling.
+    pub queue_sample_interval: Option<Duration>,
+    /// Have Stage 3 return records in the order the producer sent them,
+    /// undoing the reordering caused by feedback bounces.  Each record
+    /// is tagged with its input position ([`Record::seq`]) either way.
+    pub preserve_order: bool,
+    /// Have every stage append itself to [`Record::trail`].  Off by
+    /// default to keep records small.
+    pub record_history: bool,
+    /// Number of Stage 2 threads sharing its input (0 is treated as 1).
+    /// With more than one, records reach Stage 3 out of order.
+    pub stage2_workers: usize,
+    /// Have Stage 3 emit records strictly in input order, however many
+    /// Stage 2 workers there are, using a reorder buffer that holds at
+    /// most this many early arrivals.  Unlike `preserve_order`, the tee
+    /// and partial results see the ordered stream too.  Not compatible
+    /// with `fan_out`.  `None` disables it.
+    pub reorder_buffer: Option<usize>,
+    /// Warn (once per channel) when a send into a forward channel blocks
+    /// for longer than this, a sign that `channel_bound` is undersized.
+    /// The channels warned about are returned in
+    /// [`PipelineOutput::backpressure_warnings`].  `None` disables it.
+    pub backpressure_threshold: Option<Duration>,
+    /// Make stage `.0` panic when it processes record id `.1`, for chaos
+    /// testing.  The run then fails with [`PipelineError::StagePanicked`].
+    pub inject_panic: Option<(StageId, u32)>,
+    /// One-to-many transform applied by Stage 2; see [`FanOut`] for how
+    /// it affects completeness checks.
+    pub fan_out: Option<FanOut>,
+    /// Start at `channel_bound` and grow it under sustained
+    /// backpressure; see [`AdaptiveBound`].  `None` keeps the bound
+    /// fixed.  Ignored by [`run_batched_pipeline`].
+    pub adaptive_bound: Option<AdaptiveBound>,
+    /// Told about every record's progress through the run.  `None`
+    /// disables the callbacks.
+    pub observer: Option<Arc<dyn PipelineObserver + Send + Sync>>,
+}
+
+/// Lets the pipeline grow its channel bound when sends keep blocking.
+///
+/// The input is fed through in runs of `batch_size` records, each over
+/// freshly created channels.  When at least `grow_after` sends of a run
+/// blocked for longer than `threshold`, the bound is doubled for the
+/// next run, up to `max_bound`.  The bound finally used is returned in
+/// [`PipelineOutput::channel_bound`].
+#[derive(Debug, Clone, Copy)]
+pub struct AdaptiveBound {
+    /// Largest bound the channels may grow to.
+    pub max_bound: usize,
+    /// Records per run between two resizes (at least 1).
+    pub batch_size: u32,
+    /// Blocked sends within one run that trigger growth (at least 1).
+    pub grow_after: u64,
+    /// How long a send must block to count.  `backpressure_threshold`
+    /// takes precedence if it is set.
+    pub threshold: Duration,
+}
+
+/// Bounded retry with exponential backoff for the producer's sends.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Total send attempts per record, including the first (at least 1).
+    pub attempts: u32,
+    /// Wait after the first failed attempt; doubled after each retry.
+    pub initial_backoff: Duration,
+    /// Upper bound on the wait between two attempts.
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 10,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(100),
+        }
+    }
 }
 
 impl Default for PipelineConfig {
@@ -21,11 +146,351 @@ impl Default for PipelineConfig {
         PipelineConfig {
             num_records: NUM_RECORDS,
             channel_bound: CHANNEL_BOUND,
+            rate_limit: None,
+            tee: None,
+            max_runtime: None,
+            stage2_delay: None,
+            send_retry: None,
+            drop_rate: 0.0,
+            seed: 0,
+            warmup_records: 0,
+            event_log: None,
+            queue_sample_interval: None,
+            preserve_order: false,
+            record_history: false,
+            stage2_workers: 1,
+            reorder_buffer: None,
+            backpressure_threshold: None,
+            inject_panic: None,
+            fan_out: None,
+            adaptive_bound: None,
+            observer: None,
+        }
+    }
+}
+
+/// Reason a pipeline run was aborted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PipelineError {
+    /// Stage 1's input stayed full for the whole retry budget.
+    SendFailed { id: u32, attempts: u32 },
+    /// Stage 1 stopped accepting records without the run being cancelled.
+    Stage1Closed { id: u32 },
+    /// A stage panicked; `record` is the id it was last working on.
+    StagePanicked {
+        stage: StageId,
+        record: Option<u32>,
+        message: String,
+    },
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineError::SendFailed { id, attempts } => write!(
+                f,
+                "producer could not send record {} after {} attempts",
+                id, attempts
+            ),
+            PipelineError::Stage1Closed { id } => {
+                write!(f, "stage 1 closed before record {} was sent", id)
+            }
+            PipelineError::StagePanicked {
+                stage,
+                record: Some(id),
+                message,
+            } => write!(f, "stage {} panicked on record {}: {}", stage, id, message),
+            PipelineError::StagePanicked {
+                stage,
+                record: None,
+                message,
+            } => write!(f, "stage {} panicked before its first record: {}", stage, message),
+        }
+    }
+}
+
+impl std::error::Error for PipelineError {}
+
+/// Outcome of a pipeline run.
+#[derive(Debug)]
+pub struct PipelineOutput<T = Vec<Record>> {
+    /// Records collected by Stage 3.  Partial if `timed_out` is set.
+    pub results: T,
+    /// `true` if `max_runtime` elapsed before the pipeline drained.
+    pub timed_out: bool,
+    /// Ids the producer dropped on purpose because of `drop_rate`, in
+    /// input order.
+    pub dropped: Vec<u32>,
+    /// Throughput of the run after the warm-up.
+    pub metrics: Metrics,
+    /// Chronological event log; empty unless `event_log` was set.
+    pub events: Vec<Event>,
+    /// Queue-depth time series; empty unless `queue_sample_interval`
+    /// was set.
+    pub queue_depths: Vec<QueueDepthSample>,
+    /// Channels whose sends blocked past `backpressure_threshold`, in the
+    /// order they were first warned about.
+    pub backpressure_warnings: Vec<Queue>,
+    /// Sends that blocked past `backpressure_threshold`; 0 unless it
+    /// was set.
+    pub blocked_sends: u64,
+    /// The channel bound in effect at the end of the run.  Only differs
+    /// from `config.channel_bound` under `adaptive_bound`.
+    pub channel_bound: usize,
+}
+
+/// A misconfiguration caught by [`PipelineBuilder::build`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildError {
+    /// The declared stage count does not match the stage functions.
+    StageCount { expected: usize, got: usize },
+    /// A zero-capacity forward channel would turn every hand-off into a
+    /// rendezvous, which the producer's non-blocking sends cannot make.
+    ZeroChannelBound,
+    /// `fan_out` gives several records (or none) the same input
+    /// position, which `reorder_buffer` cannot put back in order.
+    FanOutWithReorder,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::StageCount { expected, got } => {
+                write!(f, "pipeline has {} stages, got {}", expected, got)
+            }
+            BuildError::ZeroChannelBound => write!(f, "channel_bound must be at least 1"),
+            BuildError::FanOutWithReorder => {
+                write!(f, "fan_out cannot be combined with reorder_buffer")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// Validating builder for a [`Pipeline`].
+///
+/// Checks the stage count and channel wiring up front, so a bad
+/// configuration is rejected before any thread is spawned.
+#[derive(Debug, Clone, Default)]
+pub struct PipelineBuilder {
+    config: PipelineConfig,
+    stages: Option<usize>,
+}
+
+impl PipelineBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use `config` for the run.
+    pub fn config(mut self, config: PipelineConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Declare the number of stages the caller expects to wire up.
+    /// Defaults to [`STAGE_COUNT`].
+    pub fn stages(mut self, stages: usize) -> Self {
+        self.stages = Some(stages);
+        self
+    }
+
+    /// Validate the configuration.
+    pub fn build(self) -> Result<Pipeline, BuildError> {
+        let got = self.stages.unwrap_or(STAGE_COUNT);
+        if got != STAGE_COUNT {
+            return Err(BuildError::StageCount {
+                expected: STAGE_COUNT,
+                got,
+            });
+        }
+        if self.config.channel_bound == 0 {
+            return Err(BuildError::ZeroChannelBound);
         }
+        if self.config.fan_out.is_some() && self.config.reorder_buffer.is_some() {
+            return Err(BuildError::FanOutWithReorder);
+        }
+        Ok(Pipeline {
+            config: self.config,
+        })
     }
 }
 
-/// Build and run the 3-stage pipeline, returning collected results.
+/// A validated pipeline, ready to run.
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    config: PipelineConfig,
+}
+
+impl Pipeline {
+    /// Run over records `1..=num_records`, like [`run_pipeline_with`].
+    pub fn run(self) -> Result<PipelineOutput, PipelineError> {
+        run_pipeline_with(self.config)
+    }
+
+    /// Run over the given `records`, like [`run_pipeline_from`].
+    pub fn run_from<I>(self, records: I) -> Result<PipelineOutput, PipelineError>
+    where
+        I: IntoIterator<Item = Record>,
+    {
+        run_pipeline_from(self.config, records)
+    }
+}
+
+/// Build and run the 3-stage pipeline with the default configuration,
+/// returning collected results.
+pub fn run_pipeline() -> Result<Vec<Record>, PipelineError> {
+    run_pipeline_with(PipelineConfig::default()).map(|output| output.results)
+}
+
+/// Build and run the 3-stage pipeline over records `1..=num_records`,
+/// returning collected results.
+pub fn run_pipeline_with(config: PipelineConfig) -> Result<PipelineOutput, PipelineError> {
+    let records = (1..=config.num_records).map(Record::new);
+    run_pipeline_from(config, records)
+}
+
+/// Run the pipeline over the given `records` instead of generating them.
+/// `config.num_records` is ignored.
+pub fn run_pipeline_from<I>(
+    config: PipelineConfig,
+    records: I,
+) -> Result<PipelineOutput, PipelineError>
+where
+    I: IntoIterator<Item = Record>,
+{
+    run_adaptive(config, records, StageContext::default())
+}
+
+/// Run the pipeline over records from several logical batches, with
+/// Stage 3 partitioning the results by [`Record::batch_id`].
+pub fn run_batched_pipeline<I>(
+    config: PipelineConfig,
+    records: I,
+) -> Result<PipelineOutput<HashMap<u32, Vec<Record>>>, PipelineError>
+where
+    I: IntoIterator<Item = Record>,
+{
+    run_stages(config, records, stage::stage3_by_batch, StageContext::default())
+}
+
+/// A pipeline run on a background thread; see [`spawn_pipeline`].
+///
+/// Embedders can either block with [`wait_timeout`](Self::wait_timeout)
+/// or poll [`try_results`](Self::try_results) from their own loop.
+#[derive(Debug)]
+pub struct PipelineHandle {
+    cancel: CancelToken,
+    partial: PartialResults,
+    done: Receiver<Result<PipelineOutput, PipelineError>>,
+    /// Taken once the thread has been joined.
+    thread: Option<JoinHandle<()>>,
+}
+
+/// How [`PipelineHandle::wait_timeout`] ended.
+#[derive(Debug)]
+pub enum RunOutcome {
+    /// The run finished (or failed) within the timeout.
+    Finished(Result<PipelineOutput, PipelineError>),
+    /// The timeout fired first.  Holds the records Stage 3 had collected
+    /// by then.
+    TimedOut(Vec<Record>),
+}
+
+/// Start a run over records `1..=num_records` on a background thread,
+/// like [`run_pipeline_with`], without waiting for it to finish.
+pub fn spawn_pipeline(config: PipelineConfig) -> PipelineHandle {
+    let base = StageContext {
+        partial: PartialResults::enabled(),
+        ..StageContext::default()
+    };
+    let (cancel, partial) = (base.cancel.clone(), base.partial.clone());
+    let (done_tx, done) = std::sync::mpsc::channel();
+    let thread = thread::Builder::new()
+        .name("pipeline".into())
+        .spawn(move || {
+            let records = (1..=config.num_records).map(Record::new);
+            let _ = done_tx.send(run_adaptive(config, records, base));
+        })
+        .expect("failed to spawn pipeline");
+    PipelineHandle {
+        cancel,
+        partial,
+        done,
+        thread: Some(thread),
+    }
+}
+
+impl PipelineHandle {
+    /// Returns `true` until the run has finished, successfully or not.
+    pub fn is_running(&self) -> bool {
+        self.thread.as_ref().is_some_and(|thread| !thread.is_finished())
+    }
+
+    /// Ask the run to stop early.  It still finishes with the records
+    /// collected so far, which the polling methods then return.
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    /// Take the run's output without blocking.
+    ///
+    /// Returns `None` while the run is still going, and once the output
+    /// has already been taken.
+    pub fn try_output(&mut self) -> Option<Result<PipelineOutput, PipelineError>> {
+        match self.done.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                self.join();
+                None
+            }
+        }
+    }
+
+    /// Take the collected records without blocking, like
+    /// [`try_output`](Self::try_output).  Also returns `None` if the run
+    /// failed; use `try_output` to see the error.
+    pub fn try_results(&mut self) -> Option<Vec<Record>> {
+        self.try_output()?.ok().map(|output| output.results)
+    }
+
+    /// Wait up to `timeout` for the run to finish.
+    ///
+    /// On timeout the run is cancelled and the records collected so far
+    /// are returned.  The run is not joined: a stage that is stuck (e.g.
+    /// on a full tee) may keep its thread alive.
+    ///
+    /// # Panics
+    ///
+    /// If the output was already taken with a polling method.
+    pub fn wait_timeout(mut self, timeout: Duration) -> RunOutcome {
+        match self.done.recv_timeout(timeout) {
+            Ok(result) => {
+                self.join();
+                RunOutcome::Finished(result)
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                self.cancel();
+                RunOutcome::TimedOut(self.partial.snapshot())
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                self.join();
+                panic!("pipeline output was already taken");
+            }
+        }
+    }
+
+    /// Join the run's thread, re-raising its panic if it had one.
+    fn join(&mut self) {
+        if let Some(Err(panic)) = self.thread.take().map(JoinHandle::join) {
+            panic::resume_unwind(panic);
+        }
+    }
+}
+
+/// Build and run the 3-stage pipeline, with `collect` as Stage 3.
 ///
 /// The pipeline topology:
 ///
@@ -35,9 +500,27 @@ impl Default for PipelineConfig {
 ///                                |--- [feedback] ----------|
 /// ```
 ///
-/// All channels are `sync_channel` with a small bound.
-pub fn run_pipeline() -> Vec<Record> {
-    let config = PipelineConfig::default();
+/// The forward channels are `sync_channel` with a small bound.  The
+/// feedback channel is unbounded so Stage 2 can never block on it while
+/// Stage 1 is blocked sending to Stage 2.  With several
+/// `stage2_workers`, Stage 2 is a pool of threads sharing `s1_to_s2`'s
+/// receiver.
+///
+/// If the producer gives up on a send (see `send_retry`) the run is
+/// cancelled, the stages are joined and the error is returned.
+///
+/// `base` carries the shared handles a caller wants to keep hold of,
+/// such as its cancel token; the rest of the context comes from `config`.
+fn run_stages<I, T>(
+    config: PipelineConfig,
+    records: I,
+    collect: fn(Receiver<Record>, Option<SyncSender<Record>>, StageContext) -> T,
+    base: StageContext,
+) -> Result<PipelineOutput<T>, PipelineError>
+where
+    I: IntoIterator<Item = Record>,
+    T: Send + 'static,
+{
     let bound = config.channel_bound;
 
     // Forward channels (bounded).
@@ -45,43 +528,885 @@ pub fn run_pipeline() -> Vec<Record> {
     let (s1_to_s2_tx, s1_to_s2_rx) = sync_channel::<Record>(bound);
     let (s2_to_s3_tx, s2_to_s3_rx) = sync_channel::<Record>(bound);
 
-    // Feedback channel (bounded).
-    let (feedback_tx, feedback_rx) = sync_channel::<Record>(bound);
+    // Feedback channel (unbounded).
+    let (feedback_tx, feedback_rx) = channel::<Record>();
+
+    let start = Instant::now();
+    let ctx = StageContext {
+        deadline: config.max_runtime.map(|max| start + max),
+        stage2_delay: config.stage2_delay,
+        fan_out: config.fan_out,
+        preserve_order: config.preserve_order,
+        observer: config.observer.clone(),
+        reorder_buffer: config.reorder_buffer,
+        record_history: config.record_history,
+        inject_panic: config.inject_panic,
+        backpressure: config
+            .backpressure_threshold
+            .map(BackpressureMonitor::new)
+            .unwrap_or_default(),
+        metrics: MetricsRecorder::new(config.warmup_records),
+        events: config
+            .event_log
+            .map(|log| EventRecorder::new(log, start))
+            .unwrap_or_default(),
+        ..base
+    };
 
     // --- Spawn pipeline stages ---
 
+    let s1_ctx = ctx.clone();
     let s1 = thread::Builder::new()
         .name("stage-1".into())
         .spawn(move || {
-            stage::stage1(input_rx, s1_to_s2_tx, feedback_rx);
+            let guard = s1_ctx.clone();
+            guarded(1, &guard, || stage::stage1(input_rx, s1_to_s2_tx, feedback_rx, s1_ctx))
         })
         .expect("failed to spawn stage 1");
 
-    let s2 = thread::Builder::new()
-        .name("stage-2".into())
-        .spawn(move || {
-            stage::stage2(s1_to_s2_rx, s2_to_s3_tx, feedback_tx);
-        })
-        .expect("failed to spawn stage 2");
+    let workers = config.stage2_workers.max(1);
+    let s2: Vec<_> = if workers == 1 {
+        let s2_ctx = ctx.clone();
+        let s2 = thread::Builder::new()
+            .name("stage-2".into())
+            .spawn(move || {
+                let guard = s2_ctx.clone();
+                guarded(2, &guard, || stage::stage2(s1_to_s2_rx, s2_to_s3_tx, feedback_tx, s2_ctx))
+            })
+            .expect("failed to spawn stage 2");
+        vec![s2]
+    } else {
+        let shared_rx = Arc::new(Mutex::new(s1_to_s2_rx));
+        let pool = (0..workers)
+            .map(|worker| {
+                let (rx, tx) = (Arc::clone(&shared_rx), s2_to_s3_tx.clone());
+                let feedback_tx = feedback_tx.clone();
+                let s2_ctx = ctx.clone();
+                thread::Builder::new()
+                    .name(format!("stage-2-{}", worker))
+                    .spawn(move || {
+                        let guard = s2_ctx.clone();
+                        guarded(2, &guard, || stage::stage2_shared(rx, tx, feedback_tx, s2_ctx))
+                    })
+                    .expect("failed to spawn stage 2")
+            })
+            .collect();
+        // Only the workers may hold these, so that Stage 3 sees the
+        // hang-up once the last of them exits.
+        drop(s2_to_s3_tx);
+        drop(feedback_tx);
+        pool
+    };
 
+    let tee = config.tee;
+    let s3_ctx = ctx.clone();
     let s3 = thread::Builder::new()
         .name("stage-3".into())
-        .spawn(move || -> Vec<Record> {
-            stage::stage3(s2_to_s3_rx)
+        .spawn(move || {
+            let guard = s3_ctx.clone();
+            guarded(3, &guard, || collect(s2_to_s3_rx, tee, s3_ctx))
         })
         .expect("failed to spawn stage 3");
 
+    let sampling_done = Arc::new(AtomicBool::new(false));
+    let sampler = config.queue_sample_interval.map(|interval| {
+        let queues = ctx.queues.clone();
+        let done = Arc::clone(&sampling_done);
+        thread::Builder::new()
+            .name("queue-sampler".into())
+            .spawn(move || metrics::sample_queue_depths(&queues, interval, start, &done))
+            .expect("failed to spawn queue sampler")
+    });
+
     // --- Producer: feed records into Stage 1 ---
-    for i in 1..=config.num_records {
-        let record = Record::new(i);
-        input_tx.send(record).expect("producer send failed");
+    let interval = config
+        .rate_limit
+        .filter(|&rate| rate > 0)
+        .map(|rate| Duration::from_secs(1) / rate);
+
+    let mut rng = Rng::new(config.seed);
+    let mut dropped = Vec::new();
+    let kept = records.into_iter().filter(|record| {
+        let drop = config.drop_rate > 0.0 && rng.next_f32() < config.drop_rate;
+        if drop {
+            dropped.push(record.id);
+            if let Some(observer) = &config.observer {
+                observer.on_record_dead_lettered(record.id);
+            }
+        }
+        !drop
+    });
+
+    let mut failure = None;
+    ctx.events.record(EventKind::ProducerStart);
+    // The record count is tracked as `u64`: `records` may hold more than
+    // `u32::MAX` items (ids only need to be unique per batch).
+    for (sent, mut record) in (0u64..).zip(kept) {
+        record.seq = sent;
+        if ctx.cancel.is_cancelled() {
+            break;
+        }
+        if let Some(interval) = interval {
+            // Fixed-interval pacing: the n-th record (0-based) is
+            // released no earlier than `n * interval` after the first.
+            let n = u32::try_from(sent).unwrap_or(u32::MAX);
+            let due = start.checked_add(interval.saturating_mul(n));
+            let now = Instant::now();
+            if let Some(due) = due.filter(|&due| due > now) {
+                thread::sleep(due - now);
+            }
+        }
+        let (id, send_started) = (record.id, Instant::now());
+        let sent_in = match &config.send_retry {
+            Some(policy) => send_with_retry(&input_tx, record, policy),
+            None => input_tx
+                .send(record)
+                .map_err(|err| TrySendError::Disconnected(err.0)),
+        };
+        match sent_in {
+            Ok(()) => {
+                if let Some(observer) = &ctx.observer {
+                    observer.on_record_produced(id);
+                }
+                ctx.backpressure.observe(Queue::Input, send_started.elapsed());
+                ctx.queues.sent(Queue::Input);
+            }
+            // Stage 1 hung up because the run was cancelled.
+            Err(_) if ctx.cancel.is_cancelled() => break,
+            Err(TrySendError::Full(record)) => {
+                failure = Some(PipelineError::SendFailed {
+                    id: record.id,
+                    attempts: config.send_retry.map_or(1, |policy| policy.attempts.max(1)),
+                });
+                ctx.cancel.cancel();
+                break;
+            }
+            Err(TrySendError::Disconnected(record)) => {
+                eprintln!("[producer] downstream closed after {} records", sent);
+                failure = Some(PipelineError::Stage1Closed { id: record.id });
+                break;
+            }
+        }
     }
     drop(input_tx); // close the input channel to signal EOF
 
     // --- Wait for the pipeline to complete ---
-    s1.join().expect("stage 1 panicked");
-    s2.join().expect("stage 2 panicked");
-    let results = s3.join().expect("stage 3 panicked");
+    // A panic is reported ahead of `failure`, which it likely caused.
+    let s1 = s1.join().expect("stage 1 guard panicked");
+    let s2: Vec<_> = s2
+        .into_iter()
+        .map(|worker| worker.join().expect("stage 2 guard panicked"))
+        .collect();
+    let s3 = s3.join().expect("stage 3 guard panicked");
+    ctx.events.record(EventKind::PipelineEnd);
+    sampling_done.store(true, Ordering::SeqCst);
+    let queue_depths = sampler
+        .map(|s| s.join().expect("queue sampler panicked"))
+        .unwrap_or_default();
+
+    s1?;
+    for worker in s2 {
+        worker?;
+    }
+    let results = s3?;
+    if let Some(err) = failure {
+        return Err(err);
+    }
+    Ok(PipelineOutput {
+        results,
+        timed_out: ctx.cancel.is_cancelled()
+            && ctx.deadline.is_some_and(|deadline| Instant::now() >= deadline),
+        dropped,
+        metrics: ctx.metrics.snapshot(),
+        events: ctx.events.snapshot(),
+        queue_depths,
+        backpressure_warnings: ctx.backpressure.warned(),
+        blocked_sends: ctx.backpressure.blocked_sends(),
+        channel_bound: bound,
+    })
+}
 
-    results
+/// [`run_stages`] with the regular Stage 3, honouring
+/// `config.adaptive_bound`.
+///
+/// Under an adaptive bound each slice of the input is a run of its own,
+/// sharing `base` (and so its cancel token).  The outputs are
+/// concatenated; event and queue-depth timestamps are relative to the
+/// start of their own slice.
+fn run_adaptive<I>(
+    config: PipelineConfig,
+    records: I,
+    base: StageContext,
+) -> Result<PipelineOutput, PipelineError>
+where
+    I: IntoIterator<Item = Record>,
+{
+    let Some(adaptive) = config.adaptive_bound else {
+        return run_stages(config, records, stage::stage3, base);
+    };
+
+    let start = Instant::now();
+    let mut bound = config.channel_bound;
+    let mut records = records.into_iter().peekable();
+    let mut output = PipelineOutput {
+        results: Vec::new(),
+        timed_out: false,
+        dropped: Vec::new(),
+        metrics: Metrics::default(),
+        events: Vec::new(),
+        queue_depths: Vec::new(),
+        backpressure_warnings: Vec::new(),
+        blocked_sends: 0,
+        channel_bound: bound,
+    };
+
+    for run_no in 0u64.. {
+        if records.peek().is_none() || base.cancel.is_cancelled() {
+            break;
+        }
+        let max_runtime = match config.max_runtime {
+            Some(max) => match max.checked_sub(start.elapsed()) {
+                Some(left) => Some(left),
+                None => {
+                    output.timed_out = true;
+                    break;
+                }
+            },
+            None => None,
+        };
+        let slice: Vec<Record> = records
+            .by_ref()
+            .take(adaptive.batch_size.max(1) as usize)
+            .collect();
+        let run = run_stages(
+            PipelineConfig {
+                channel_bound: bound,
+                max_runtime,
+                seed: config.seed.wrapping_add(run_no),
+                warmup_records: if run_no == 0 { config.warmup_records } else { 0 },
+                backpressure_threshold: config.backpressure_threshold.or(Some(adaptive.threshold)),
+                adaptive_bound: None,
+                ..config.clone()
+            },
+            slice,
+            stage::stage3,
+            base.clone(),
+        )?;
+
+        output.results.extend(run.results);
+        output.timed_out |= run.timed_out;
+        output.dropped.extend(run.dropped);
+        output.metrics.measured_records += run.metrics.measured_records;
+        output.metrics.elapsed += run.metrics.elapsed;
+        output.events.extend(run.events);
+        output.queue_depths.extend(run.queue_depths);
+        for queue in run.backpressure_warnings {
+            if !output.backpressure_warnings.contains(&queue) {
+                output.backpressure_warnings.push(queue);
+            }
+        }
+        output.blocked_sends += run.blocked_sends;
+        output.channel_bound = bound;
+
+        if run.blocked_sends >= adaptive.grow_after.max(1) {
+            bound = (bound * 2).min(adaptive.max_bound).max(bound);
+        }
+    }
+    Ok(output)
+}
+
+/// Run the body of `stage`, turning a panic into
+/// [`PipelineError::StagePanicked`].
+///
+/// The panic is logged with the record the stage was working on, and
+/// the run is cancelled so the other stages shut down.
+fn guarded<T>(
+    stage: StageId,
+    ctx: &StageContext,
+    body: impl FnOnce() -> T,
+) -> Result<T, PipelineError> {
+    panic::catch_unwind(AssertUnwindSafe(body)).map_err(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "non-string panic payload".into());
+        let record = ctx.current.get(stage);
+        eprintln!(
+            "[stage{}] panicked on record {:?}: {}; cancelling the run",
+            stage, record, message
+        );
+        ctx.cancel.cancel();
+        PipelineError::StagePanicked {
+            stage,
+            record,
+            message,
+        }
+    })
+}
+
+/// Try to send `record`, backing off while the channel is full.
+///
+/// Gives the record back in the error once `policy.attempts` are used
+/// up, or immediately if the receiver has hung up.
+fn send_with_retry(
+    tx: &SyncSender<Record>,
+    mut record: Record,
+    policy: &RetryPolicy,
+) -> Result<(), TrySendError<Record>> {
+    let mut backoff = policy.initial_backoff;
+    let mut attempts_left = policy.attempts.max(1);
+    loop {
+        match tx.try_send(record) {
+            Err(TrySendError::Full(rejected)) if attempts_left > 1 => {
+                attempts_left -= 1;
+                record = rejected;
+                thread::sleep(backoff);
+                backoff = (backoff * 2).min(policy.max_backoff);
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicU32;
+    use crate::worker::check_completeness;
+
+    #[test]
+    fn default_config_processes_every_record() {
+        let output = run_pipeline_with(PipelineConfig {
+            num_records: 50,
+            ..PipelineConfig::default()
+        })
+        .unwrap();
+        assert_eq!(output.results.len(), 50);
+        assert!(!output.timed_out);
+    }
+
+    #[test]
+    fn rate_limit_paces_the_producer() {
+        let start = Instant::now();
+        let output = run_pipeline_with(PipelineConfig {
+            num_records: 10,
+            rate_limit: Some(50),
+            ..PipelineConfig::default()
+        })
+        .unwrap();
+        // 10 records at 50/s: the last is released 9 * 20ms after the first.
+        assert!(start.elapsed() >= Duration::from_millis(180));
+        assert_eq!(output.results.len(), 10);
+    }
+
+    #[test]
+    fn max_runtime_returns_partial_results() {
+        let start = Instant::now();
+        let output = run_pipeline_with(PipelineConfig {
+            num_records: 200,
+            max_runtime: Some(Duration::from_millis(100)),
+            stage2_delay: Some(Duration::from_millis(5)),
+            ..PipelineConfig::default()
+        })
+        .unwrap();
+        assert!(output.timed_out);
+        assert!(!output.results.is_empty());
+        assert!(output.results.len() < 200);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn stalled_run_reports_partial_results_on_timeout() {
+        // Nobody drains the tee, so Stage 3 stalls once it is full and
+        // the run can never finish on its own.
+        let (tee_tx, tee_rx) = sync_channel::<Record>(1);
+        let running = spawn_pipeline(PipelineConfig {
+            num_records: 50,
+            tee: Some(tee_tx),
+            ..PipelineConfig::default()
+        });
+
+        match running.wait_timeout(Duration::from_millis(200)) {
+            RunOutcome::TimedOut(partial) => {
+                assert!(!partial.is_empty());
+                assert!(partial.len() < 50);
+                assert!(partial.iter().all(Record::verify));
+            }
+            RunOutcome::Finished(result) => panic!("stalled run finished: {:?}", result),
+        }
+        // Unblock Stage 3 so the background run can wind down.
+        drop(tee_rx);
+    }
+
+    #[test]
+    fn handle_can_be_polled_until_done() {
+        let mut handle = spawn_pipeline(PipelineConfig {
+            num_records: 40,
+            ..PipelineConfig::default()
+        });
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let results = loop {
+            if let Some(results) = handle.try_results() {
+                break results;
+            }
+            assert!(Instant::now() < deadline, "pipeline did not finish");
+            thread::sleep(Duration::from_millis(1));
+        };
+        assert_eq!(results.len(), 40);
+        assert!(handle.try_output().is_none());
+        while handle.is_running() {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn cancelled_handle_finishes_with_partial_results() {
+        let mut handle = spawn_pipeline(PipelineConfig {
+            num_records: 500,
+            stage2_delay: Some(Duration::from_millis(2)),
+            ..PipelineConfig::default()
+        });
+        assert!(handle.is_running());
+        thread::sleep(Duration::from_millis(20));
+        handle.cancel();
+        while handle.is_running() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        let results = handle.try_results().unwrap();
+        assert!(results.len() < 500);
+    }
+
+    #[test]
+    fn spawned_run_finishes_within_the_timeout() {
+        let running = spawn_pipeline(PipelineConfig {
+            num_records: 20,
+            ..PipelineConfig::default()
+        });
+        match running.wait_timeout(Duration::from_secs(5)) {
+            RunOutcome::Finished(result) => assert_eq!(result.unwrap().results.len(), 20),
+            RunOutcome::TimedOut(partial) => panic!("timed out with {} results", partial.len()),
+        }
+    }
+
+    #[test]
+    fn preserve_order_restores_input_order_after_bounces() {
+        // Every tenth id is bounced back to Stage 1; a slow Stage 2 keeps
+        // the forward channel full so its successors can overtake it.
+        let ordered = run_pipeline_with(PipelineConfig {
+            num_records: 100,
+            stage2_delay: Some(Duration::from_micros(200)),
+            preserve_order: true,
+            ..PipelineConfig::default()
+        })
+        .unwrap()
+        .results;
+        let ids: Vec<u32> = ordered.iter().map(|r| r.id).collect();
+        assert_eq!(ids, (1..=100).collect::<Vec<_>>());
+        assert!(ordered.iter().filter(|r| r.retry_count > 0).count() >= 10);
+    }
+
+    #[test]
+    fn reorder_buffer_keeps_fifo_across_parallel_workers() {
+        let config = PipelineConfig {
+            num_records: 200,
+            stage2_workers: 4,
+            reorder_buffer: Some(100),
+            stage2_delay: Some(Duration::from_micros(200)),
+            ..PipelineConfig::default()
+        };
+        let (tee, audit) = sync_channel(200);
+        let output = run_pipeline_with(PipelineConfig {
+            tee: Some(tee),
+            ..config.clone()
+        })
+        .unwrap();
+        let seqs: Vec<u64> = output.results.iter().map(|r| r.seq).collect();
+        assert_eq!(seqs, (0..200).collect::<Vec<_>>());
+        let teed: Vec<u64> = audit.try_iter().map(|r| r.seq).collect();
+        assert_eq!(teed, seqs);
+        assert!(check_completeness(&output.results, 200).is_complete());
+
+        let err = PipelineBuilder::new()
+            .config(PipelineConfig {
+                fan_out: Some(FanOut::new(|record| vec![record])),
+                ..config
+            })
+            .build()
+            .unwrap_err();
+        assert_eq!(err, BuildError::FanOutWithReorder);
+    }
+
+    #[test]
+    fn record_history_traces_feedback_bounces() {
+        let output = run_pipeline_with(PipelineConfig {
+            num_records: 20,
+            record_history: true,
+            ..PipelineConfig::default()
+        })
+        .unwrap();
+        let trail = |id: u32| {
+            let record = output.results.iter().find(|r| r.id == id).unwrap();
+            record.trail.clone()
+        };
+        assert_eq!(trail(7), vec![1, 2, 3]);
+        // Id 10 is bounced by Stage 2 once and goes through Stage 1 again.
+        assert_eq!(trail(10), vec![1, 2, 1, 2, 3]);
+
+        let plain = run_pipeline_with(PipelineConfig {
+            num_records: 20,
+            ..PipelineConfig::default()
+        })
+        .unwrap();
+        assert!(plain.results.iter().all(|r| r.trail.is_empty()));
+    }
+
+    #[test]
+    fn blocked_sends_trigger_a_backpressure_warning() {
+        let config = PipelineConfig {
+            num_records: 20,
+            channel_bound: 1,
+            stage2_delay: Some(Duration::from_millis(5)),
+            ..PipelineConfig::default()
+        };
+        let output = run_pipeline_with(PipelineConfig {
+            backpressure_threshold: Some(Duration::from_millis(1)),
+            ..config.clone()
+        })
+        .unwrap();
+        let warnings = &output.backpressure_warnings;
+        assert!(warnings.contains(&Queue::Stage1ToStage2), "{:?}", warnings);
+        // At most one warning per channel.
+        assert!(warnings.len() <= 3, "{:?}", warnings);
+
+        let quiet = run_pipeline_with(config).unwrap();
+        assert!(quiet.backpressure_warnings.is_empty());
+    }
+
+    #[test]
+    fn adaptive_bound_grows_under_backpressure() {
+        let output = run_pipeline_with(PipelineConfig {
+            num_records: 200,
+            channel_bound: 1,
+            stage2_delay: Some(Duration::from_micros(500)),
+            adaptive_bound: Some(AdaptiveBound {
+                max_bound: 8,
+                batch_size: 25,
+                grow_after: 1,
+                threshold: Duration::from_micros(100),
+            }),
+            ..PipelineConfig::default()
+        })
+        .unwrap();
+        assert!(output.channel_bound > 1, "{}", output.channel_bound);
+        assert!(output.channel_bound <= 8, "{}", output.channel_bound);
+        assert!(output.blocked_sends > 0);
+
+        let report = check_completeness(&output.results, 200);
+        assert!(report.missing.is_empty(), "{:?}", report.missing);
+        assert!(report.duplicates.is_empty(), "{:?}", report.duplicates);
+    }
+
+    #[derive(Debug, Default)]
+    struct CountingObserver {
+        produced: AtomicU32,
+        entered: AtomicU32,
+        completed: AtomicU32,
+        dead_lettered: AtomicU32,
+    }
+
+    impl PipelineObserver for CountingObserver {
+        fn on_record_produced(&self, _id: u32) {
+            self.produced.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn on_stage_enter(&self, _stage: u8, _id: u32) {
+            self.entered.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn on_record_completed(&self, _record: &Record) {
+            self.completed.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn on_record_dead_lettered(&self, _id: u32) {
+            self.dead_lettered.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn observer_sees_every_transition() {
+        let observer = Arc::new(CountingObserver::default());
+        let output = run_pipeline_with(PipelineConfig {
+            num_records: 100,
+            drop_rate: 0.2,
+            seed: 7,
+            observer: Some(observer.clone()),
+            ..PipelineConfig::default()
+        })
+        .unwrap();
+
+        let count = |counter: &AtomicU32| counter.load(Ordering::SeqCst) as usize;
+        assert_eq!(count(&observer.completed), output.results.len());
+        assert_eq!(count(&observer.dead_lettered), output.dropped.len());
+        assert_eq!(count(&observer.produced), 100 - output.dropped.len());
+        // Three stages each, plus two extra visits per feedback bounce.
+        assert!(count(&observer.entered) >= 3 * output.results.len());
+    }
+
+    #[test]
+    fn stage_panic_is_reported_with_the_record_id() {
+        let err = run_pipeline_with(PipelineConfig {
+            num_records: 50,
+            inject_panic: Some((2, 17)),
+            ..PipelineConfig::default()
+        })
+        .unwrap_err();
+        match &err {
+            PipelineError::StagePanicked {
+                stage: 2,
+                record: Some(17),
+                message,
+            } => assert!(message.contains("injected panic"), "{}", message),
+            other => panic!("expected a stage 2 panic, got {:?}", other),
+        }
+        assert!(err.to_string().contains("record 17"), "{}", err);
+    }
+
+    #[test]
+    fn fan_out_can_double_or_drop_records() {
+        let doubled = run_pipeline_with(PipelineConfig {
+            num_records: 30,
+            fan_out: Some(FanOut::new(|record| vec![record.clone(), record])),
+            ..PipelineConfig::default()
+        })
+        .unwrap()
+        .results;
+        assert_eq!(doubled.len(), 60);
+        let report = check_completeness(&doubled, 30);
+        assert!(report.missing.is_empty());
+        assert_eq!(report.duplicates, (1..=30).collect::<Vec<_>>());
+
+        let evens = run_pipeline_with(PipelineConfig {
+            num_records: 30,
+            fan_out: Some(FanOut::new(|record| {
+                if record.id % 2 == 0 {
+                    vec![record]
+                } else {
+                    Vec::new()
+                }
+            })),
+            ..PipelineConfig::default()
+        })
+        .unwrap()
+        .results;
+        assert_eq!(evens.len(), 15);
+    }
+
+    #[test]
+    fn tee_receives_every_record() {
+        let (tee_tx, tee_rx) = sync_channel::<Record>(2);
+        let drain = thread::spawn(move || tee_rx.iter().map(|r| r.id).collect::<Vec<_>>());
+
+        let results = run_pipeline_with(PipelineConfig {
+            num_records: 30,
+            tee: Some(tee_tx),
+            ..PipelineConfig::default()
+        })
+        .unwrap()
+        .results;
+
+        let mut teed = drain.join().unwrap();
+        teed.sort_unstable();
+        assert_eq!(teed, (1..=30).collect::<Vec<_>>());
+        assert_eq!(results.len(), 30);
+    }
+
+    #[test]
+    fn batched_run_partitions_results() {
+        let records = (1..=20)
+            .map(|id| Record::in_batch(id, 1))
+            .chain((1..=15).map(|id| Record::in_batch(id, 2)));
+        let batches = run_batched_pipeline(PipelineConfig::default(), records)
+            .unwrap()
+            .results;
+
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[&1].len(), 20);
+        assert_eq!(batches[&2].len(), 15);
+        assert!(batches[&2].iter().all(|r| r.batch_id == 2));
+
+        let completeness = crate::worker::check_batch_completeness(&batches, 20);
+        assert!(completeness[&1].is_complete());
+        assert_eq!(completeness[&2].missing, (16..=20).collect::<Vec<_>>());
+        assert_eq!(completeness[&2].gaps, vec![(16, 20)]);
+    }
+
+    #[test]
+    fn throttled_stage1_is_retried_until_it_catches_up() {
+        // A one-slot input with a slow Stage 2 keeps the producer's
+        // channel full for several retries per record.
+        let output = run_pipeline_with(PipelineConfig {
+            num_records: 20,
+            channel_bound: 1,
+            stage2_delay: Some(Duration::from_millis(3)),
+            send_retry: Some(RetryPolicy {
+                attempts: 50,
+                initial_backoff: Duration::from_millis(1),
+                max_backoff: Duration::from_millis(5),
+            }),
+            ..PipelineConfig::default()
+        })
+        .unwrap();
+        assert_eq!(output.results.len(), 20);
+    }
+
+    #[test]
+    fn slow_pipeline_blocks_instead_of_failing_by_default() {
+        // The producer waits far longer than any retry budget would
+        // allow, but without `send_retry` it just waits.
+        let output = run_pipeline_with(PipelineConfig {
+            num_records: 5,
+            channel_bound: 1,
+            stage2_delay: Some(Duration::from_millis(400)),
+            ..PipelineConfig::default()
+        })
+        .unwrap();
+        assert_eq!(output.results.len(), 5);
+    }
+
+    #[test]
+    fn exhausted_retries_abort_the_run() {
+        let err = run_pipeline_with(PipelineConfig {
+            num_records: 50,
+            channel_bound: 1,
+            stage2_delay: Some(Duration::from_millis(20)),
+            send_retry: Some(RetryPolicy {
+                attempts: 2,
+                initial_backoff: Duration::from_micros(100),
+                max_backoff: Duration::from_micros(100),
+            }),
+            ..PipelineConfig::default()
+        })
+        .unwrap_err();
+        assert!(matches!(err, PipelineError::SendFailed { attempts: 2, .. }), "{:?}", err);
+    }
+
+    #[test]
+    fn builder_rejects_bad_wiring_before_running() {
+        let err = PipelineBuilder::new().stages(2).build().unwrap_err();
+        assert_eq!(err, BuildError::StageCount { expected: 3, got: 2 });
+
+        let err = PipelineBuilder::new()
+            .config(PipelineConfig {
+                channel_bound: 0,
+                ..PipelineConfig::default()
+            })
+            .build()
+            .unwrap_err();
+        assert_eq!(err, BuildError::ZeroChannelBound);
+    }
+
+    #[test]
+    fn built_pipeline_runs() {
+        let output = PipelineBuilder::new()
+            .config(PipelineConfig {
+                num_records: 25,
+                ..PipelineConfig::default()
+            })
+            .stages(STAGE_COUNT)
+            .build()
+            .unwrap()
+            .run()
+            .unwrap();
+        assert_eq!(output.results.len(), 25);
+    }
+
+    #[test]
+    fn drop_rate_drops_and_reports_about_half() {
+        let output = run_pipeline_with(PipelineConfig {
+            num_records: 1000,
+            drop_rate: 0.5,
+            seed: 42,
+            ..PipelineConfig::default()
+        })
+        .unwrap();
+
+        let arrived = output.results.len();
+        assert!((400..=600).contains(&arrived), "{} arrived", arrived);
+        assert_eq!(arrived + output.dropped.len(), 1000);
+
+        let report =
+            crate::worker::check_completeness_with_drops(&output.results, 1000, &output.dropped);
+        assert!(report.is_complete(), "{:?}", report.missing);
+        assert_eq!(report.dropped, output.dropped);
+    }
+
+    #[test]
+    fn warmup_records_are_excluded_from_metrics() {
+        let output = run_pipeline_with(PipelineConfig {
+            num_records: 100,
+            warmup_records: 20,
+            ..PipelineConfig::default()
+        })
+        .unwrap();
+        assert_eq!(output.results.len(), 100);
+        assert_eq!(output.metrics.measured_records, 80);
+        assert!(output.metrics.throughput() > 0.0);
+    }
+
+    #[test]
+    fn ids_at_the_top_of_the_range_flow_through() {
+        let records = (u32::MAX - 9..=u32::MAX).map(Record::new);
+        let mut ids: Vec<u32> = run_pipeline_from(PipelineConfig::default(), records)
+            .unwrap()
+            .results
+            .iter()
+            .map(|r| r.id)
+            .collect();
+        ids.sort_unstable();
+        assert_eq!(ids, (u32::MAX - 9..=u32::MAX).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn event_log_is_bracketed_by_start_and_end() {
+        let output = run_pipeline_with(PipelineConfig {
+            num_records: 20,
+            event_log: Some(EventLogConfig {
+                capacity: 100,
+                sample_every: 5,
+            }),
+            ..PipelineConfig::default()
+        })
+        .unwrap();
+
+        let events = &output.events;
+        assert_eq!(events.first().unwrap().kind, EventKind::ProducerStart);
+        assert_eq!(events.last().unwrap().kind, EventKind::PipelineEnd);
+        assert!(events.windows(2).all(|w| w[0].at <= w[1].at));
+        assert!(events
+            .iter()
+            .any(|e| e.kind == EventKind::Leave { stage: 3, id: 5 }));
+    }
+
+    #[test]
+    fn queue_depths_are_sampled_within_bounds() {
+        let output = run_pipeline_with(PipelineConfig {
+            num_records: 50,
+            stage2_delay: Some(Duration::from_millis(1)),
+            queue_sample_interval: Some(Duration::from_millis(2)),
+            ..PipelineConfig::default()
+        })
+        .unwrap();
+
+        let series = &output.queue_depths;
+        assert!(series.len() > 1);
+        assert!(series.windows(2).all(|w| w[0].at <= w[1].at));
+        for sample in series {
+            for depth in [sample.input, sample.stage1_to_stage2, sample.stage2_to_stage3] {
+                assert!(depth <= 50, "{:?}", sample);
+            }
+        }
+        let last = series.last().unwrap();
+        assert_eq!((last.input, last.stage1_to_stage2, last.stage2_to_stage3), (0, 0, 0));
+    }
 }
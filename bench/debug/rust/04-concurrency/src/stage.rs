@@ -1,47 +1,300 @@
-use std::sync::mpsc::{Receiver, SyncSender};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, SendError, Sender, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crate::worker::{do_work, Record};
+use crate::events::{EventRecorder, PipelineObserver};
+use crate::metrics::{BackpressureMonitor, MetricsRecorder, Queue, QueueCounters};
+use crate::worker::{do_work, Record, StageId};
 
 /// The maximum pipeline stage at which records can still be retried.
 /// Once a record's stage reaches this value, it is forwarded to the
 /// next stage regardless of its retry eligibility.
 const MAX_RETRY_STAGE: u32 = 4;
 
+/// How long a waiting stage blocks before re-checking for cancellation
+/// (and, in Stage 1, whether any records are still in flight).
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// A shared flag that asks every stage of a pipeline run to stop early.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken::default()
+    }
+
+    /// Ask the pipeline to stop.  Stages finish the record they are
+    /// working on and then shut down.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// The records Stage 3 has collected so far, readable while the run is
+/// still going.  Disabled (and free) unless created with
+/// [`PartialResults::enabled`].
+#[derive(Debug, Clone, Default)]
+pub struct PartialResults(Option<Arc<Mutex<Vec<Record>>>>);
+
+impl PartialResults {
+    pub fn enabled() -> Self {
+        PartialResults(Some(Arc::default()))
+    }
+
+    pub fn push(&self, record: &Record) {
+        if let Some(records) = &self.0 {
+            records.lock().unwrap().push(record.clone());
+        }
+    }
+
+    /// The records collected so far, in arrival order.
+    pub fn snapshot(&self) -> Vec<Record> {
+        match &self.0 {
+            Some(records) => records.lock().unwrap().clone(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// A one-to-many transform that Stage 2 applies to each record it
+/// forwards, e.g. to explode a batched record into its parts.
+///
+/// Each returned record is forwarded to Stage 3 in order; returning an
+/// empty `Vec` drops the record.  Ids are not rewritten, so outputs that
+/// share an id show up as duplicates in
+/// [`check_completeness`](crate::worker::check_completeness), and
+/// records mapped to nothing show up as missing.
+#[derive(Clone)]
+pub struct FanOut(Arc<dyn Fn(Record) -> Vec<Record> + Send + Sync>);
+
+impl FanOut {
+    pub fn new(f: impl Fn(Record) -> Vec<Record> + Send + Sync + 'static) -> Self {
+        FanOut(Arc::new(f))
+    }
+
+    pub fn apply(&self, record: Record) -> Vec<Record> {
+        (self.0)(record)
+    }
+}
+
+impl fmt::Debug for FanOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("FanOut(..)")
+    }
+}
+
+/// The id of the record each stage most recently started on, for
+/// crash reports.
+#[derive(Debug, Clone, Default)]
+pub struct CurrentRecords(Arc<[AtomicU64; 3]>);
+
+impl CurrentRecords {
+    /// Note that `stage` (1-3) started processing record `id`.
+    pub fn set(&self, stage: StageId, id: u32) {
+        // Stored as `id + 1` so that 0 can mean "none yet".
+        self.0[usize::from(stage - 1)].store(u64::from(id) + 1, Ordering::SeqCst);
+    }
+
+    /// The record `stage` most recently started on, if any.
+    pub fn get(&self, stage: StageId) -> Option<u32> {
+        let stored = self.0[usize::from(stage - 1)].load(Ordering::SeqCst);
+        stored.checked_sub(1).map(|id| id as u32)
+    }
+}
+
+/// State shared by all stages of one pipeline run.
+#[derive(Debug, Clone, Default)]
+pub struct StageContext {
+    /// Records sent to Stage 2 that it has not yet forwarded to
+    /// Stage 3.
+    pub in_flight: Arc<AtomicU32>,
+    /// Cancels the run when set.
+    pub cancel: CancelToken,
+    /// Stage 3 cancels the run once this instant has passed.
+    pub deadline: Option<Instant>,
+    /// Artificial per-record delay in Stage 2, simulating a slow
+    /// transformation.
+    pub stage2_delay: Option<Duration>,
+    /// Applied by Stage 2 to every record it forwards.
+    pub fan_out: Option<FanOut>,
+    /// Stage 3 sorts its results by [`Record::seq`] before returning.
+    pub preserve_order: bool,
+    /// Stage 3 emits records in [`Record::seq`] order as they arrive,
+    /// holding at most this many early arrivals back.
+    pub reorder_buffer: Option<usize>,
+    /// Every stage appends itself to [`Record::trail`].
+    pub record_history: bool,
+    /// The record each stage is working on.
+    pub current: CurrentRecords,
+    /// Panic in stage `.0` on record id `.1`, for chaos testing.
+    pub inject_panic: Option<(StageId, u32)>,
+    /// Stage 3 reports every completed record here.
+    pub metrics: MetricsRecorder,
+    /// Sampled per-record trace; disabled by default.
+    pub events: EventRecorder,
+    /// Hand-off counts on the forward channels, for queue-depth sampling.
+    pub queues: QueueCounters,
+    /// Warns when a forward send blocks for too long.
+    pub backpressure: BackpressureMonitor,
+    /// Stage 3 mirrors every collected record here.
+    pub partial: PartialResults,
+    /// Notified as records move through the stages.
+    pub observer: Option<Arc<dyn PipelineObserver + Send + Sync>>,
+}
+
+impl StageContext {
+    /// Note that `stage` is processing `record`: in `current`, and in the
+    /// record's trail if history is being recorded.
+    fn visit(&self, record: &mut Record, stage: StageId) {
+        self.current.set(stage, record.id);
+        if let Some(observer) = &self.observer {
+            observer.on_stage_enter(stage, record.id);
+        }
+        if self.inject_panic == Some((stage, record.id)) {
+            panic!("injected panic in stage {} on record {}", stage, record.id);
+        }
+        if self.record_history {
+            record.trail.push(stage);
+        }
+    }
+
+    /// Send `record` into the forward channel `queue`, reporting how
+    /// long the send blocked to the backpressure monitor.
+    fn send_forward(
+        &self,
+        output: &SyncSender<Record>,
+        record: Record,
+        queue: Queue,
+    ) -> Result<(), SendError<Record>> {
+        if !self.backpressure.is_enabled() {
+            return output.send(record);
+        }
+        let started = Instant::now();
+        let sent = output.send(record);
+        if sent.is_ok() {
+            self.backpressure.observe(queue, started.elapsed());
+        }
+        sent
+    }
+}
+
+/// Why a stage stopped running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StageExit {
+    /// All input was processed and forwarded.
+    Completed,
+    /// A downstream receiver hung up before all input was forwarded.
+    /// The stage dropped its own receivers so the shutdown propagates
+    /// upstream.
+    DownstreamClosed,
+    /// The run was cancelled, e.g. because its deadline passed.
+    Cancelled,
+}
+
 /// Stage 1: Ingestion.
 ///
 /// Reads raw records from `input`, processes them, and forwards to
 /// Stage 2 via `output`.  Also listens for feedback from Stage 2 on
 /// `feedback_rx` and re-processes those records.
+///
+/// `ctx.in_flight` counts records sent to Stage 2 that it has not yet
+/// forwarded to Stage 3.  Once input is exhausted, Stage 1 keeps
+/// serving feedback until that count drops to zero, since no further
+/// feedback can arrive after that.
+///
+/// If Stage 2 hangs up, Stage 1 stops and returns
+/// [`StageExit::DownstreamClosed`].
 pub fn stage1(
     input: Receiver<Record>,
     output: SyncSender<Record>,
     feedback_rx: Receiver<Record>,
-) {
+    ctx: StageContext,
+) -> StageExit {
+    let in_flight = &ctx.in_flight;
     let mut records_sent = 0u32;
     let mut feedback_processed = 0u32;
 
-    // --- Primary loop: drain all input records ---
-    for mut record in input {
-        do_work(&mut record, "stage1");
-        output.send(record).expect("stage1 -> stage2 send failed");
-        records_sent += 1;
-    }
-
-    // --- Feedback loop: reprocess records that Stage 2 sent back ---
-    for mut record in feedback_rx {
+    let redo = |mut record: Record| {
+        ctx.events.enter(1, record.id);
         record.mark_retry();
+        ctx.visit(&mut record, 1);
         do_work(&mut record, "stage1-redo");
-        output.send(record).expect("stage1 -> stage2 redo send failed");
-        feedback_processed += 1;
-    }
+        ctx.events.leave(1, record.id);
+        let sent = ctx.send_forward(&output, record, Queue::Stage1ToStage2);
+        if sent.is_ok() {
+            ctx.queues.sent(Queue::Stage1ToStage2);
+        }
+        sent
+    };
+
+    let exit = 'run: {
+        // --- Primary loop: drain all input records, serving any pending
+        // feedback between them ---
+        for mut record in input.iter() {
+            ctx.queues.received(Queue::Input);
+            if ctx.cancel.is_cancelled() {
+                break 'run StageExit::Cancelled;
+            }
+
+            while let Ok(bounced) = feedback_rx.try_recv() {
+                if redo(bounced).is_err() {
+                    break 'run StageExit::DownstreamClosed;
+                }
+                feedback_processed += 1;
+            }
 
-    // Drop the output sender to signal downstream that Stage 1 is done.
+            ctx.events.enter(1, record.id);
+            ctx.visit(&mut record, 1);
+            do_work(&mut record, "stage1");
+            in_flight.fetch_add(1, Ordering::SeqCst);
+            ctx.events.leave(1, record.id);
+            if ctx.send_forward(&output, record, Queue::Stage1ToStage2).is_err() {
+                break 'run StageExit::DownstreamClosed;
+            }
+            ctx.queues.sent(Queue::Stage1ToStage2);
+            records_sent += 1;
+        }
+
+        // --- Feedback loop: reprocess records that Stage 2 sent back ---
+        while in_flight.load(Ordering::SeqCst) > 0 {
+            if ctx.cancel.is_cancelled() {
+                break 'run StageExit::Cancelled;
+            }
+            match feedback_rx.recv_timeout(POLL_INTERVAL) {
+                Ok(bounced) => {
+                    if redo(bounced).is_err() {
+                        break 'run StageExit::DownstreamClosed;
+                    }
+                    feedback_processed += 1;
+                }
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        StageExit::Completed
+    };
+
+    // Drop the output sender to signal downstream that Stage 1 is done,
+    // and the input receiver so the producer sees an early shutdown.
     drop(output);
+    drop(input);
 
     eprintln!(
-        "[stage1] finished: sent={}, feedback={}",
-        records_sent, feedback_processed
+        "[stage1] finished: sent={}, feedback={}, exit={:?}",
+        records_sent, feedback_processed, exit
     );
+
+    exit
 }
 
 /// Stage 2: Transformation.
@@ -51,28 +304,94 @@ pub fn stage1(
 /// reprocessing via `feedback_tx`, simulating a "needs retry" signal.
 ///
 /// The retry only happens while the record's `stage` is below
-/// `MAX_RETRY_STAGE`, preventing infinite loops.
+/// `MAX_RETRY_STAGE`, preventing infinite loops.  Each forwarded
+/// record is released from `ctx.in_flight`.
+///
+/// With `ctx.fan_out` set, a forwarded record is first passed through
+/// it and each resulting record is sent on instead.
+///
+/// If Stage 3 or Stage 1 hangs up, Stage 2 stops and returns
+/// [`StageExit::DownstreamClosed`].
 pub fn stage2(
     input: Receiver<Record>,
     output: SyncSender<Record>,
-    feedback_tx: SyncSender<Record>,
-) {
+    feedback_tx: Sender<Record>,
+    ctx: StageContext,
+) -> StageExit {
+    let exit = transform(|| input.recv().ok(), output, feedback_tx, ctx);
+    drop(input);
+    exit
+}
+
+/// Stage 2 as one of several workers sharing `input`; otherwise the
+/// same as [`stage2`].  Records are handed to whichever worker asks
+/// first, so they leave the pool out of order.
+pub fn stage2_shared(
+    input: Arc<Mutex<Receiver<Record>>>,
+    output: SyncSender<Record>,
+    feedback_tx: Sender<Record>,
+    ctx: StageContext,
+) -> StageExit {
+    transform(|| input.lock().unwrap().recv().ok(), output, feedback_tx, ctx)
+}
+
+/// The body of Stage 2, reading records from `next` until it returns
+/// `None`.
+fn transform(
+    mut next: impl FnMut() -> Option<Record>,
+    output: SyncSender<Record>,
+    feedback_tx: Sender<Record>,
+    ctx: StageContext,
+) -> StageExit {
     let mut forwarded = 0u32;
     let mut feedback_sent = 0u32;
+    let mut exit = StageExit::Completed;
 
-    for mut record in input {
+    while let Some(mut record) = next() {
+        if ctx.cancel.is_cancelled() {
+            exit = StageExit::Cancelled;
+            break;
+        }
+        ctx.queues.received(Queue::Stage1ToStage2);
+        ctx.events.enter(2, record.id);
+        if let Some(delay) = ctx.stage2_delay {
+            thread::sleep(delay);
+        }
+
+        ctx.visit(&mut record, 2);
         do_work(&mut record, "stage2");
+        ctx.events.leave(2, record.id);
 
         let needs_retry = record.id % 10 == 0 && record.stage < MAX_RETRY_STAGE;
 
         if needs_retry {
-            feedback_tx
-                .send(record)
-                .expect("stage2 -> stage1 feedback send failed");
+            if feedback_tx.send(record).is_err() {
+                exit = StageExit::DownstreamClosed;
+                break;
+            }
             feedback_sent += 1;
         } else {
-            output.send(record).expect("stage2 -> stage3 send failed");
-            forwarded += 1;
+            let id = record.id;
+            let outputs = match &ctx.fan_out {
+                Some(fan_out) => fan_out.apply(record),
+                None => vec![record],
+            };
+            if let (true, Some(observer)) = (outputs.is_empty(), &ctx.observer) {
+                observer.on_record_dead_lettered(id);
+            }
+            for out in outputs {
+                if ctx.send_forward(&output, out, Queue::Stage2ToStage3).is_err() {
+                    exit = StageExit::DownstreamClosed;
+                    break;
+                }
+                ctx.queues.sent(Queue::Stage2ToStage3);
+                forwarded += 1;
+            }
+            if exit == StageExit::DownstreamClosed {
+                break;
+            }
+            // Released once per input record, however many it became.
+            ctx.in_flight.fetch_sub(1, Ordering::SeqCst);
         }
     }
 
@@ -80,20 +399,77 @@ pub fn stage2(
     drop(output);
 
     eprintln!(
-        "[stage2] finished: forwarded={}, feedback={}",
-        forwarded, feedback_sent
+        "[stage2] finished: forwarded={}, feedback={}, exit={:?}",
+        forwarded, feedback_sent, exit
     );
+
+    exit
 }
 
 /// Stage 3: Output / collection.
 ///
 /// Collects all processed records into a vector.  Also performs a basic
 /// integrity check on each record as it arrives.
-pub fn stage3(input: Receiver<Record>) -> Vec<Record> {
+///
+/// If `tee` is set, a copy of each record is sent to it before the
+/// record is collected.  A full tee channel blocks Stage 3, applying
+/// backpressure; if the tee receiver hangs up, teeing stops but
+/// collection continues.
+///
+/// Once `ctx.deadline` passes, Stage 3 cancels the run and returns the
+/// records collected so far.
+///
+/// With `ctx.preserve_order` set, the results are returned in input
+/// order (by [`Record::seq`]) rather than arrival order.
+///
+/// With `ctx.reorder_buffer` set, records are also teed and collected
+/// in input order, as soon as every earlier record has been.  Records
+/// that arrive early wait in a buffer of that capacity; if it fills up
+/// (a record is stuck far behind the rest), the earliest buffered
+/// record is released, giving up strict order for the stragglers.
+pub fn stage3(
+    input: Receiver<Record>,
+    mut tee: Option<SyncSender<Record>>,
+    ctx: StageContext,
+) -> Vec<Record> {
     let mut results = Vec::new();
     let mut integrity_errors = 0u32;
+    let mut reorder = ctx.reorder_buffer.map(ReorderBuffer::new);
+
+    let mut emit = |record: Record| {
+        if let Some(sink) = &tee {
+            if sink.send(record.clone()).is_err() {
+                eprintln!("[stage3] tee receiver closed; no longer teeing");
+                tee = None;
+            }
+        }
+        ctx.metrics.record_completion();
+        if let Some(observer) = &ctx.observer {
+            observer.on_record_completed(&record);
+        }
+        ctx.events.leave(3, record.id);
+        ctx.partial.push(&record);
+        results.push(record);
+    };
+
+    loop {
+        if ctx.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            eprintln!("[stage3] deadline passed; cancelling the run");
+            ctx.cancel.cancel();
+        }
+        if ctx.cancel.is_cancelled() {
+            break;
+        }
+
+        let mut record = match input.recv_timeout(POLL_INTERVAL) {
+            Ok(record) => record,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        ctx.queues.received(Queue::Stage2ToStage3);
+        ctx.events.enter(3, record.id);
+        ctx.visit(&mut record, 3);
 
-    for record in input {
         if !record.verify() {
             integrity_errors += 1;
             eprintln!(
@@ -101,7 +477,13 @@ pub fn stage3(input: Receiver<Record>) -> Vec<Record> {
                 record.id
             );
         }
-        results.push(record);
+        match &mut reorder {
+            Some(buffer) => buffer.push(record).for_each(&mut emit),
+            None => emit(record),
+        }
+    }
+    if let Some(buffer) = reorder {
+        buffer.drain().for_each(&mut emit);
     }
 
     eprintln!(
@@ -110,5 +492,121 @@ pub fn stage3(input: Receiver<Record>) -> Vec<Record> {
         integrity_errors
     );
 
+    if ctx.preserve_order {
+        results.sort_by_key(|record| record.seq);
+    }
     results
 }
+
+/// Releases records in [`Record::seq`] order, holding back at most
+/// `capacity` that arrived ahead of their turn.
+#[derive(Debug)]
+struct ReorderBuffer {
+    capacity: usize,
+    next_seq: u64,
+    waiting: BTreeMap<u64, Record>,
+    overflowed: bool,
+}
+
+impl ReorderBuffer {
+    fn new(capacity: usize) -> Self {
+        ReorderBuffer {
+            capacity,
+            next_seq: 0,
+            waiting: BTreeMap::new(),
+            overflowed: false,
+        }
+    }
+
+    /// Accept `record`, returning the records that are now due.
+    fn push(&mut self, record: Record) -> std::vec::IntoIter<Record> {
+        let mut due = Vec::new();
+        if record.seq < self.next_seq {
+            // Its turn was skipped when the buffer overflowed.
+            due.push(record);
+        } else if record.seq == self.next_seq {
+            self.next_seq += 1;
+            due.push(record);
+        } else {
+            self.waiting.insert(record.seq, record);
+            if self.waiting.len() > self.capacity {
+                if !self.overflowed {
+                    eprintln!(
+                        "[stage3] reorder buffer full; releasing records past seq {} early",
+                        self.next_seq
+                    );
+                    self.overflowed = true;
+                }
+                let (seq, earliest) = self.waiting.pop_first().expect("buffer is not empty");
+                self.next_seq = seq + 1;
+                due.push(earliest);
+            }
+        }
+        while let Some(record) = self.waiting.remove(&self.next_seq) {
+            self.next_seq += 1;
+            due.push(record);
+        }
+        due.into_iter()
+    }
+
+    /// Release everything still waiting, in order.
+    fn drain(self) -> impl Iterator<Item = Record> {
+        self.waiting.into_values()
+    }
+}
+
+/// Stage 3 for multiplexed runs: collects like [`stage3`], then
+/// partitions the results by [`Record::batch_id`].
+///
+/// Records keep their arrival order within each batch.
+pub fn stage3_by_batch(
+    input: Receiver<Record>,
+    tee: Option<SyncSender<Record>>,
+    ctx: StageContext,
+) -> HashMap<u32, Vec<Record>> {
+    let mut batches: HashMap<u32, Vec<Record>> = HashMap::new();
+    for record in stage3(input, tee, ctx) {
+        batches.entry(record.batch_id).or_default().push(record);
+    }
+    batches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{channel, sync_channel};
+
+    #[test]
+    fn dropped_consumer_unwinds_without_panic() {
+        let (input_tx, input_rx) = sync_channel::<Record>(5);
+        let (s1_to_s2_tx, s1_to_s2_rx) = sync_channel::<Record>(5);
+        let (s2_to_s3_tx, s2_to_s3_rx) = sync_channel::<Record>(5);
+        let (feedback_tx, feedback_rx) = channel::<Record>();
+        let ctx = StageContext::default();
+
+        let s1_ctx = ctx.clone();
+        let s1 = thread::spawn(move || stage1(input_rx, s1_to_s2_tx, feedback_rx, s1_ctx));
+        let s2 = thread::spawn(move || stage2(s1_to_s2_rx, s2_to_s3_tx, feedback_tx, ctx));
+
+        // The consumer takes a few results and then bails.
+        let consumer = thread::spawn(move || {
+            for _ in 0..3 {
+                s2_to_s3_rx.recv().unwrap();
+            }
+        });
+
+        let mut produced = 0;
+        for i in 1..=1000 {
+            if input_tx.send(Record::new(i)).is_err() {
+                break;
+            }
+            produced += 1;
+        }
+        drop(input_tx);
+
+        consumer.join().unwrap();
+        assert_eq!(s2.join().unwrap(), StageExit::DownstreamClosed);
+        assert_eq!(s1.join().unwrap(), StageExit::DownstreamClosed);
+        assert!(produced < 1000);
+    }
+}
@@ -1,3 +1,8 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// A pipeline stage number (1-3), as in [`crate::events::EventKind`].
+pub type StageId = u8;
+
 /// A processed record flowing through the pipeline.
 #[derive(Debug, Clone)]
 pub struct Record {
@@ -6,11 +11,36 @@ pub struct Record {
     pub stage: u32,
     pub checksum: u32,
     pub retry_count: u32,
+    /// Logical batch this record belongs to.  Ids only need to be unique
+    /// within a batch.
+    pub batch_id: u32,
+    /// Position at which the producer fed this record into the
+    /// pipeline.  See [`PipelineConfig::preserve_order`].
+    ///
+    /// [`PipelineConfig::preserve_order`]: crate::pipeline::PipelineConfig::preserve_order
+    pub seq: u64,
+    /// Stages that processed this record, in order, including repeat
+    /// visits after a feedback bounce.  Empty unless
+    /// [`PipelineConfig::record_history`] is set.
+    ///
+    /// [`PipelineConfig::record_history`]: crate::pipeline::PipelineConfig::record_history
+    pub trail: Vec<StageId>,
 }
 
 impl Record {
     pub fn new(id: u32) -> Self {
-        let payload = format!("data-{:04}", id);
+        Record::in_batch(id, 0)
+    }
+
+    /// Create a record belonging to batch `batch_id`.
+    pub fn in_batch(id: u32, batch_id: u32) -> Self {
+        let mut record = Record::with_payload(id, format!("data-{:04}", id));
+        record.batch_id = batch_id;
+        record
+    }
+
+    /// Create a record carrying an explicit `payload`.
+    pub fn with_payload(id: u32, payload: String) -> Self {
         let checksum = compute_checksum(&payload);
         Record {
             id,
@@ -18,6 +48,9 @@ impl Record {
             stage: 0,
             checksum,
             retry_count: 0,
+            batch_id: 0,
+            seq: 0,
+            trail: Vec::new(),
         }
     }
 
@@ -56,11 +89,20 @@ pub fn do_work(record: &mut Record, stage_name: &str) {
 /// Validate a batch of output records, returning the number of valid
 /// records and a list of any that failed validation.
 pub fn validate_batch(records: &[Record]) -> (usize, Vec<u32>) {
+    validate_batch_with(records, Record::verify)
+}
+
+/// Validate a batch of output records using a caller-supplied
+/// `is_valid` check in place of the checksum.
+pub fn validate_batch_with<F>(records: &[Record], is_valid: F) -> (usize, Vec<u32>)
+where
+    F: Fn(&Record) -> bool,
+{
     let mut valid = 0;
     let mut invalid_ids = Vec::new();
 
     for record in records {
-        if record.verify() {
+        if is_valid(record) {
             valid += 1;
         } else {
             invalid_ids.push(record.id);
@@ -70,26 +112,431 @@ pub fn validate_batch(records: &[Record]) -> (usize, Vec<u32>) {
     (valid, invalid_ids)
 }
 
+/// Fold `results` into a single value, e.g. a sum or a count-by-key.
+pub fn reduce_results<T, F>(results: &[Record], init: T, f: F) -> T
+where
+    F: Fn(T, &Record) -> T,
+{
+    results.iter().fold(init, f)
+}
+
+/// Parallel version of [`reduce_results`].
+///
+/// `results` is split into up to `threads` contiguous chunks, each
+/// folded on its own thread starting from `init()`, and the partial
+/// values are combined left-to-right with `merge`.  `init()` must be an
+/// identity for `merge` for the result to match the sequential fold.
+pub fn par_reduce_results<T, I, F, M>(
+    results: &[Record],
+    threads: usize,
+    init: I,
+    f: F,
+    merge: M,
+) -> T
+where
+    T: Send,
+    I: Fn() -> T + Sync,
+    F: Fn(T, &Record) -> T + Sync,
+    M: Fn(T, T) -> T,
+{
+    let chunk_len = results.len().div_ceil(threads.max(1)).max(1);
+
+    let partials: Vec<T> = std::thread::scope(|scope| {
+        let handles: Vec<_> = results
+            .chunks(chunk_len)
+            .map(|chunk| scope.spawn(|| chunk.iter().fold(init(), &f)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| h.join().expect("reduce worker panicked"))
+            .collect()
+    });
+
+    partials.into_iter().fold(init(), merge)
+}
+
+/// Result of [`check_completeness`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompletenessReport {
+    /// Every expected id that never appeared and was not dropped on
+    /// purpose, in ascending order.
+    pub missing: Vec<u32>,
+    /// Expected ids that never appeared because they were dropped on
+    /// purpose, in ascending order.
+    pub dropped: Vec<u32>,
+    /// Every id that appeared more than once, in ascending order.
+    pub duplicates: Vec<u32>,
+    /// `missing` collapsed into contiguous `(start, end)` ranges, both
+    /// ends inclusive.
+    pub gaps: Vec<(u32, u32)>,
+}
+
+impl CompletenessReport {
+    /// Returns `true` if no ids are missing or duplicated.
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty() && self.duplicates.is_empty()
+    }
+}
+
 /// Check that every record id from 1..=expected_count appears
 /// exactly once in the output (no duplicates, no missing).
-pub fn check_completeness(records: &[Record], expected_count: u32) -> (Vec<u32>, Vec<u32>) {
-    let mut seen = vec![0u32; expected_count as usize + 1];
+pub fn check_completeness(records: &[Record], expected_count: u32) -> CompletenessReport {
+    check_completeness_with_drops(records, expected_count, &[])
+}
+
+/// Like [`check_completeness`], but ids in `dropped` (for example
+/// [`crate::pipeline::PipelineOutput::dropped`]) that never appeared are
+/// reported as `dropped` rather than `missing`.
+pub fn check_completeness_with_drops(
+    records: &[Record],
+    expected_count: u32,
+    dropped: &[u32],
+) -> CompletenessReport {
+    let mut tracker = CompletenessTracker::with_drops(expected_count, dropped);
     for record in records {
-        if record.id as usize <= expected_count as usize {
-            seen[record.id as usize] += 1;
+        tracker.observe(record.id);
+    }
+    tracker.report()
+}
+
+/// Incremental version of [`check_completeness`]: fed ids one at a time,
+/// e.g. as Stage 3 emits them, and queryable at any point.
+///
+/// [`missing_count`](Self::missing_count),
+/// [`duplicate_count`](Self::duplicate_count) and
+/// [`is_complete`](Self::is_complete) are kept up to date by
+/// [`observe`](Self::observe), so polling them is O(1).
+#[derive(Debug, Clone)]
+pub struct CompletenessTracker {
+    expected_count: u32,
+    /// Occurrences of each in-range id seen so far.  Only observed ids
+    /// are stored, so memory grows with the ids seen, not with
+    /// `expected_count`.
+    seen: BTreeMap<u32, u32>,
+    /// In-range ids dropped on purpose.
+    intentional: BTreeSet<u32>,
+    /// Ids seen more than once.
+    duplicates: BTreeSet<u32>,
+    /// Distinct in-range ids seen so far.
+    distinct_seen: u32,
+    /// Ids in `intentional` not seen so far.
+    dropped_unseen: u32,
+}
+
+impl CompletenessTracker {
+    /// Track ids `1..=expected_count`.
+    pub fn new(expected_count: u32) -> Self {
+        CompletenessTracker::with_drops(expected_count, &[])
+    }
+
+    /// Like [`new`](Self::new), with ids in `dropped` reported as
+    /// `dropped` rather than `missing`, as in
+    /// [`check_completeness_with_drops`].
+    pub fn with_drops(expected_count: u32, dropped: &[u32]) -> Self {
+        let intentional: BTreeSet<u32> = dropped
+            .iter()
+            .copied()
+            .filter(|id| (1..=expected_count).contains(id))
+            .collect();
+        CompletenessTracker {
+            expected_count,
+            seen: BTreeMap::new(),
+            duplicates: BTreeSet::new(),
+            distinct_seen: 0,
+            dropped_unseen: intentional.len() as u32,
+            intentional,
+        }
+    }
+
+    /// Note one occurrence of `id`.  Ids outside the expected range are
+    /// ignored.
+    pub fn observe(&mut self, id: u32) {
+        if !(1..=self.expected_count).contains(&id) {
+            return;
+        }
+        let count = self.seen.entry(id).or_insert(0);
+        match *count {
+            0 => {
+                self.distinct_seen += 1;
+                if self.intentional.contains(&id) {
+                    self.dropped_unseen -= 1;
+                }
+            }
+            1 => {
+                self.duplicates.insert(id);
+            }
+            _ => {}
         }
+        *count = count.saturating_add(1);
     }
 
-    let mut missing = Vec::new();
-    let mut duplicates = Vec::new();
+    /// Number of ids the report would list as `missing`.
+    pub fn missing_count(&self) -> u32 {
+        self.expected_count - self.distinct_seen - self.dropped_unseen
+    }
+
+    /// Number of ids seen more than once.
+    pub fn duplicate_count(&self) -> usize {
+        self.duplicates.len()
+    }
 
-    for id in 1..=expected_count {
-        match seen[id as usize] {
-            0 => missing.push(id),
-            1 => {} // correct
-            _ => duplicates.push(id),
+    /// Same as `self.report().is_complete()`, without building the
+    /// report.
+    pub fn is_complete(&self) -> bool {
+        self.missing_count() == 0 && self.duplicates.is_empty()
+    }
+
+    /// The report's `gaps` so far, found from the seen and dropped ids
+    /// alone, so this stays cheap for a huge `expected_count`.
+    pub fn gaps(&self) -> Vec<(u32, u32)> {
+        let mut accounted: Vec<u32> = self.seen.keys().chain(&self.intentional).copied().collect();
+        accounted.sort_unstable();
+        accounted.dedup();
+
+        // `next` is the first id not yet known to be accounted for; u64
+        // so it can step past `u32::MAX`.
+        let mut next = 1u64;
+        let mut gaps = Vec::new();
+        for id in accounted {
+            if u64::from(id) > next {
+                gaps.push((next as u32, id - 1));
+            }
+            next = u64::from(id) + 1;
         }
+        if next <= u64::from(self.expected_count) {
+            gaps.push((next as u32, self.expected_count));
+        }
+        gaps
     }
 
-    (missing, duplicates)
+    /// The state so far: ids not yet seen are `missing` (or `dropped`).
+    ///
+    /// This lists every missing id, so it costs time and memory in
+    /// proportion to how many are missing.
+    pub fn report(&self) -> CompletenessReport {
+        let gaps = self.gaps();
+        let missing = gaps.iter().flat_map(|&(start, end)| start..=end).collect();
+        let dropped = self
+            .intentional
+            .iter()
+            .copied()
+            .filter(|id| !self.seen.contains_key(id))
+            .collect();
+        let duplicates = self.duplicates.iter().copied().collect();
+
+        CompletenessReport {
+            missing,
+            dropped,
+            duplicates,
+            gaps,
+        }
+    }
+}
+
+/// Find "late" records: ids that arrived in `records` after a higher id
+/// had already appeared.  Returned in arrival order.
+///
+/// An empty result means the output was in non-decreasing id order.
+pub fn find_out_of_order(records: &[Record]) -> Vec<u32> {
+    let mut highest = None;
+    let mut late = Vec::new();
+
+    for record in records {
+        match highest {
+            Some(max) if record.id < max => late.push(record.id),
+            _ => highest = Some(record.id),
+        }
+    }
+
+    late
+}
+
+/// Find likely re-sends: records with identical payloads whose ids are
+/// at most `window` apart, within the same batch.
+///
+/// Each group holds the distinct ids of one cluster, sorted; clusters
+/// are chained, so 1, 3 and 5 form one group under a window of 2.
+/// Repeats of the same id are left to [`check_completeness`]'s
+/// `duplicates` and only count once here.  Groups are ordered by their
+/// first id.
+pub fn find_near_duplicates(records: &[Record], window: u32) -> Vec<Vec<u32>> {
+    let mut by_content: HashMap<(u32, &str), Vec<u32>> = HashMap::new();
+    for record in records {
+        by_content
+            .entry((record.batch_id, record.payload.as_str()))
+            .or_default()
+            .push(record.id);
+    }
+
+    let mut groups = Vec::new();
+    for mut ids in by_content.into_values() {
+        ids.sort_unstable();
+        ids.dedup();
+        let mut cluster: Vec<u32> = Vec::new();
+        for id in ids {
+            if cluster.last().is_some_and(|&last| id - last > window) {
+                if cluster.len() > 1 {
+                    groups.push(std::mem::take(&mut cluster));
+                } else {
+                    cluster.clear();
+                }
+            }
+            cluster.push(id);
+        }
+        if cluster.len() > 1 {
+            groups.push(cluster);
+        }
+    }
+    groups.sort_unstable();
+    groups
+}
+
+/// Run [`check_completeness`] separately for each batch, keyed by
+/// batch id.  Every batch is expected to contain ids
+/// `1..=expected_count`.
+pub fn check_batch_completeness(
+    batches: &HashMap<u32, Vec<Record>>,
+    expected_count: u32,
+) -> HashMap<u32, CompletenessReport> {
+    batches
+        .iter()
+        .map(|(&batch_id, records)| (batch_id, check_completeness(records, expected_count)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_batch_with_custom_validator() {
+        let records: Vec<Record> = (1..=6).map(Record::new).collect();
+        let (valid, invalid_ids) = validate_batch_with(&records, |r| r.id % 2 != 0);
+        assert_eq!(valid, 3);
+        assert_eq!(invalid_ids, vec![2, 4, 6]);
+    }
+
+    #[test]
+    fn completeness_reports_gap_ranges() {
+        let records: Vec<Record> = [1, 2, 5, 6, 10].into_iter().map(Record::new).collect();
+        let report = check_completeness(&records, 10);
+        assert_eq!(report.missing, vec![3, 4, 7, 8, 9]);
+        assert_eq!(report.gaps, vec![(3, 4), (7, 9)]);
+        assert!(report.duplicates.is_empty());
+        assert!(!report.is_complete());
+    }
+
+    #[test]
+    fn sequential_and_parallel_reduce_agree() {
+        let records: Vec<Record> = (1..=1000).map(Record::new).collect();
+        let sum = |acc: u64, r: &Record| acc + u64::from(r.id);
+
+        let sequential = reduce_results(&records, 0u64, sum);
+        let parallel = par_reduce_results(&records, 4, || 0u64, sum, |a, b| a + b);
+
+        assert_eq!(sequential, 500_500);
+        assert_eq!(parallel, sequential);
+        assert_eq!(par_reduce_results(&[], 4, || 0u64, sum, |a, b| a + b), 0);
+    }
+
+    #[test]
+    fn out_of_order_ids_are_identified() {
+        let records: Vec<Record> = [1, 2, 5, 3, 6, 4, 7].into_iter().map(Record::new).collect();
+        assert_eq!(find_out_of_order(&records), vec![3, 4]);
+
+        let ordered: Vec<Record> = (1..=5).map(Record::new).collect();
+        assert!(find_out_of_order(&ordered).is_empty());
+    }
+
+    #[test]
+    fn validate_batch_defaults_to_checksum() {
+        let mut records: Vec<Record> = (1..=3).map(Record::new).collect();
+        records[1].payload.push('!');
+        assert_eq!(validate_batch(&records), (2, vec![2]));
+    }
+
+    #[test]
+    fn ids_near_u32_max_do_not_overflow() {
+        let top: Vec<Record> = (u32::MAX - 4..=u32::MAX).map(Record::new).collect();
+        let mut tracker = CompletenessTracker::new(u32::MAX);
+        tracker.observe(1);
+        tracker.observe(u32::MAX - 3);
+        assert_eq!(
+            tracker.gaps(),
+            vec![(2, u32::MAX - 4), (u32::MAX - 2, u32::MAX)]
+        );
+        assert!(find_out_of_order(&top).is_empty());
+
+        // Out-of-range ids are ignored rather than indexing past the end.
+        let report = check_completeness(&top, 3);
+        assert_eq!(report.missing, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn tracker_stores_only_observed_ids() {
+        let mut tracker = CompletenessTracker::with_drops(u32::MAX, &[5, 0]);
+        for id in [1, 2, 2, 1_000_000, u32::MAX] {
+            tracker.observe(id);
+        }
+        assert_eq!(tracker.seen.len(), 4);
+        assert_eq!(tracker.missing_count(), u32::MAX - 5);
+        assert_eq!(tracker.duplicate_count(), 1);
+        assert_eq!(
+            tracker.gaps(),
+            vec![(3, 4), (6, 999_999), (1_000_001, u32::MAX - 1)]
+        );
+    }
+
+    #[test]
+    fn tracker_can_be_queried_mid_stream() {
+        let mut tracker = CompletenessTracker::new(6);
+        for id in [1, 3, 3] {
+            tracker.observe(id);
+        }
+        let report = tracker.report();
+        assert_eq!(report.missing, vec![2, 4, 5, 6]);
+        assert_eq!(report.gaps, vec![(2, 2), (4, 6)]);
+        assert_eq!(report.duplicates, vec![3]);
+        assert_eq!(tracker.missing_count(), 4);
+        assert_eq!(tracker.duplicate_count(), 1);
+        assert!(!tracker.is_complete());
+
+        for id in [2, 4, 5, 6, 0, 7] {
+            tracker.observe(id);
+        }
+        let report = tracker.report();
+        assert!(report.missing.is_empty());
+        assert_eq!(report.duplicates, vec![3]);
+    }
+
+    #[test]
+    fn running_counts_match_the_report() {
+        let mut tracker = CompletenessTracker::with_drops(5, &[2, 4]);
+        for id in [1, 4, 4, 5] {
+            tracker.observe(id);
+            let report = tracker.report();
+            assert_eq!(tracker.missing_count() as usize, report.missing.len());
+            assert_eq!(tracker.duplicate_count(), report.duplicates.len());
+            assert_eq!(tracker.is_complete(), report.is_complete());
+        }
+        assert_eq!(tracker.report().dropped, vec![2]);
+        assert_eq!(tracker.report().missing, vec![3]);
+    }
+
+    #[test]
+    fn near_duplicate_payloads_are_clustered() {
+        let mut records: Vec<Record> = (1..=10).map(Record::new).collect();
+        for id in [4, 5, 7] {
+            records.push(Record::with_payload(id + 20, "order #17".into()));
+        }
+        // Same payload, but too far from the cluster to be a re-send.
+        records.push(Record::with_payload(60, "order #17".into()));
+        // Same payload in another batch.
+        let mut other = Record::with_payload(25, "order #17".into());
+        other.batch_id = 1;
+        records.push(other);
+
+        assert_eq!(find_near_duplicates(&records, 2), vec![vec![24, 25, 27]]);
+        assert!(find_near_duplicates(&records, 0).is_empty());
+    }
 }
@@ -0,0 +1,28 @@
+use parser_bench::csv_parser::parse_csv;
+use pipeline_bench::csv_source::table_records;
+use pipeline_bench::pipeline::{run_pipeline_from, PipelineConfig};
+use pipeline_bench::worker::check_completeness;
+
+#[test]
+fn parsed_csv_runs_through_the_pipeline() {
+    let csv = "id,name,score\n\
+               1,alpha,10\n\
+               2,beta,20\n\
+               3,gamma,30\n\
+               10,kappa,100\n";
+    let table = parse_csv(csv).unwrap();
+
+    let records = table_records(&table, "id").unwrap();
+    let output = run_pipeline_from(PipelineConfig::default(), records).unwrap();
+
+    assert_eq!(output.results.len(), table.len());
+    let report = check_completeness(&output.results, 3);
+    assert!(report.is_complete());
+    assert!(output.results.iter().any(|r| r.payload.starts_with("2,beta,20")));
+}
+
+#[test]
+fn unknown_id_column_is_rejected() {
+    let table = parse_csv("id,name\n1,alpha\n").unwrap();
+    assert!(table_records(&table, "key").is_err());
+}